@@ -0,0 +1,101 @@
+//! compares `GameState` with the bitboard `Position`
+//! run with: cargo run --release --example bench
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tic_tac_toe::models::{Board, Cell, Expiry, GameState, Rules};
+use tic_tac_toe::position::Position;
+
+const MOVES: usize = 200_000;
+
+fn main() {
+    let rules = [
+        ("3x3, 6 marks", Rules::default()),
+        ("4x4, 3 per player", Rules::new(Board::square(4, 3), Expiry::PerPlayer(3))),
+        ("8x8, 5 in a row, 12 marks", Rules::new(Board::square(8, 5), Expiry::Global(12))),
+    ];
+    for (name, rules) in rules {
+        println!("{}", name);
+        let state = time(|| play_states(rules));
+        let position = time(|| play_positions(rules));
+        report("random playouts", state, position);
+
+        let states = positions(rules);
+        let state = time(|| {
+            states.iter().filter(|(game_state, _)| game_state.winner() != Cell::N).count()
+        });
+        let position = time(|| {
+            states.iter().filter(|(_, position)| position.winner() != Cell::N).count()
+        });
+        report("winner detection", state, position);
+    }
+}
+
+/// random games with game states, started again
+/// whenever one is won
+fn play_states(rules: Rules) -> usize {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut game_state = GameState::empty(rules);
+    for _ in 0..MOVES {
+        let moves = game_state.legal_moves();
+        if moves.is_empty() {
+            game_state = GameState::empty(rules);
+            continue;
+        }
+        game_state = game_state.turn(moves[rng.gen_range(0..moves.len())]).unwrap();
+    }
+    game_state.last as usize
+}
+
+/// the same games with positions
+fn play_positions(rules: Rules) -> usize {
+    let mut rng = StdRng::seed_from_u64(0);
+    let empty = Position::empty(rules).unwrap();
+    let mut position = empty;
+    for _ in 0..MOVES {
+        let mut moves = position.legal_moves();
+        if moves.len() == 0 {
+            position = empty;
+            continue;
+        }
+        let tile = moves.nth(rng.gen_range(0..moves.len())).unwrap();
+        position = position.play(tile);
+    }
+    position.to_state().last as usize
+}
+
+/// every position of a few random games
+fn positions(rules: Rules) -> Vec<(GameState, Position)> {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut positions = Vec::new();
+    let mut game_state = GameState::empty(rules);
+    for _ in 0..10_000 {
+        positions.push((game_state.clone(), Position::from_state(&game_state).unwrap()));
+        let moves = game_state.legal_moves();
+        if moves.is_empty() {
+            game_state = GameState::empty(rules);
+            continue;
+        }
+        game_state = game_state.turn(moves[rng.gen_range(0..moves.len())]).unwrap();
+    }
+    positions
+}
+
+fn time<T>(f: impl Fn() -> T) -> Duration {
+    let start = Instant::now();
+    black_box(f());
+    start.elapsed()
+}
+
+fn report(name: &str, state: Duration, position: Duration) {
+    println!(
+        "  {:<18} GameState {:>10.2?}  Position {:>10.2?}  {:>6.1}x",
+        name,
+        state,
+        position,
+        state.as_secs_f64() / position.as_secs_f64(),
+    );
+}
@@ -1,122 +1,135 @@
-use std::collections::HashMap;
+use rand::{Rng, RngCore};
+use rand::seq::SliceRandom;
 
-use crate::utils::get_possibilities;
-use crate::models::{Cell, GameState};
+use crate::mcts::MctsConfig;
+use crate::strategy::Strategy;
+use crate::models::{Cell, GameState, Player};
 
-pub(crate) fn turn(game_state: &GameState, temperature: f32) -> u16 {
+/// The built in strategies with their
+/// settings, see `strategy`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Level {
+    /// looks one move ahead, the difficulty is
+    /// the amount of random noise added to the
+    /// score of every move
+    Heuristic(f32),
+    /// plays one of the best moves found by the
+    /// solver. Rules too big to be solved fall
+    /// back to the heuristic without noise
+    Perfect,
+    /// searches `depth` moves ahead, see
+    /// `search::Searcher`. A node budget stops
+    /// the search sooner on big boards
+    Search { depth: u8, nodes: Option<u64> },
+    /// Monte Carlo tree search, see `mcts`
+    Mcts(MctsConfig),
+}
 
-    let mut empty_tiles = Vec::new();
-    let mut player = Cell::X;
-    for (i, &num) in game_state.array.iter().enumerate() {
-        if let Cell::N = game_state.get(i as u16) {
-            empty_tiles.push(i as u16);
-        } else if num == game_state.last {
-            player = game_state.get(i as u16);
-        }
+/// Picks the tile `strategy` plays on for
+/// `player`, or None if no move can be made or
+/// it is not `player`'s turn. All randomness
+/// comes from `rng`, so a seeded rng always
+/// picks the same moves
+pub fn choose(game_state: &GameState, player: Player, strategy: &dyn Strategy, rng: &mut dyn RngCore) -> Option<u16> {
+    if game_state.to_move() != player {
+        return None;
     }
+    strategy.choose_move(game_state, rng)
+}
 
-    let mut map = HashMap::new();
-    for tile in empty_tiles {
-        let new = game_state.turn(tile).unwrap();
-        let opportunity = calculate_opportunity(&new, player, temperature);
-        map.insert((opportunity * 1000.0) as i32, tile);
+/// Scores every legal move for the player to
+/// move with the one move lookahead heuristic,
+/// higher is better. Moves are in tile order
+/// and nothing random is added
+pub fn evaluate_moves(game_state: &GameState) -> Vec<(u16, f32)> {
+    let opponent = game_state.to_move().other().cell();
+
+    game_state
+    .legal_moves()
+    .into_iter()
+    .filter_map(|tile| {
+        let new = game_state.turn(tile).ok()?;
+        Some((tile, calculate_opportunity(&new, opponent)))
+    })
+    .collect()
+}
+
+/// Picks the tile the AI plays on, or None
+/// if no move can be made. Every move gets
+/// its own noise between -temperature and
+/// temperature added to its score. When several
+/// moves share the best score one of them is
+/// picked at random using `rng`
+pub(crate) fn turn<R: Rng + ?Sized>(game_state: &GameState, temperature: f32, rng: &mut R) -> Option<u16> {
+    let mut best: Vec<u16> = Vec::new();
+    let mut best_score = f32::NEG_INFINITY;
+    for (tile, score) in evaluate_moves(game_state) {
+        let random = if temperature > 0.0 {
+            (rng.gen::<f32>() * temperature * 2.0) - temperature 
+        } else {0.0};
+        let score = score + random;
+
+        if score > best_score {
+            best_score = score;
+            best.clear();
+        }
+        if score == best_score {
+            best.push(tile);
+        }
     }
-    
-    let selected= map.clone().into_keys().max().unwrap(); 
 
-    map[&selected]
+    best.choose(rng).copied()
 }
 
-fn calculate_opportunity(game_state: &GameState, opponent: Cell, temperature: f32) -> f32 {
-    let possibilities = get_possibilities();
+/// the heuristic score of a position for the
+/// player who is not `opponent`, the leaf
+/// evaluator of the search AI
+pub(crate) fn calculate_opportunity(game_state: &GameState, opponent: Cell) -> f32 {
     let this = if let Cell::X = opponent {
         Cell::O
     } else {
         Cell::X
     };
 
+    // expiring marks are not counted for anyone
+    let counts = game_state.board().lines().into_iter().map(|possibility| {
+        let count = |cell: Cell| possibility.iter().filter(|&&index| game_state.get(index) == cell).count() as i32;
+        (count(this), count(opponent))
+    });
+    score_lines(counts, game_state.board().win_length as i32)
+}
+
+/// scores every line from how many live marks
+/// this player and the opponent have in it.
+/// Shared by `calculate_opportunity` and the
+/// bitboard evaluation of the search AI
+pub(crate) fn score_lines(counts: impl Iterator<Item = (i32, i32)>, win_length: i32) -> f32 {
     let mut opportunity = (0.0, 0.0);
-    for possibility in possibilities {
-        
-        let mut like: i32 = 0;
-        let mut unlike: i32 = 0;
-        for index in possibility {
-            match game_state.get(index) {
-                Cell::X => {
-                    if let Cell::X = this {
-                        like += 1;
-                    } else {
-                        unlike += 1;
-                    }
-                },
-                Cell::O => {
-                    if let Cell::O = this {
-                        like += 1;
-                    } else {
-                        unlike += 1;
-                    }
-                },
-                Cell::E => {
-                    continue;
-                },
-                Cell::N => {},
-            }
-        }
+    for (like, unlike) in counts {
         if like > 0 {
             opportunity.0 += like.pow(3) as f32;
-            if like == 3 {
+            if like == win_length {
                 opportunity.0 += 8.0;
             }
-            if unlike == 2 {
+            if unlike == win_length - 1 {
                 opportunity.0 += 8.0;
             }
         }
 
-
-
-        let mut like: i32 = 0;
-        let mut unlike: i32 = 0;
-        for index in possibility {
-            match game_state.get(index) {
-                Cell::X => {
-                    if let Cell::X = opponent {
-                        like += 1;
-                    } else {
-                        unlike += 1;
-                    }
-                },
-                Cell::O => {
-                    if let Cell::O = opponent {
-                        like += 1;
-                    } else {
-                        unlike += 1;
-                    }
-                },
-                Cell::E => {
-                    continue;
-                },
-                Cell::N => {},
-            }
-        }
+        // the same line from the opponent's side
+        let (like, unlike) = (unlike, like);
         if like > 0 {
             opportunity.1 += like.pow(3) as f32;
-            if like == 2 {
+            if like == win_length - 1 {
                 opportunity.1 += 8.0;
             }
-            if unlike == 2 {
+            if unlike == win_length - 1 {
                 opportunity.1 += 8.0;
             }
         }
     }
 
-    let random = if temperature > 0.0 {
-        (rand::random::<f32>() * temperature * 2.0) - temperature 
-    } else {0.0};
-
-
-    let opportunity = (opportunity.0 - opportunity.1) + random;
-    // println!("{:?} = {opportunity}", game_state);
-    opportunity
+    opportunity.0 - opportunity.1
 }
 
 
@@ -126,9 +139,13 @@ fn calculate_opportunity(game_state: &GameState, opponent: Cell, temperature: f3
 
 #[cfg(test)]
 mod test {
-    use crate::GameState;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use crate::mcts::MctsConfig;
+    use crate::models::{Board, GameState, Player, Rules};
 
-    use super::turn;
+    use super::{choose, evaluate_moves, turn, Level};
 
 
     #[test]
@@ -144,9 +161,9 @@ mod test {
             4, 5, 0
         ];
         let game_state = GameState::from_array(array);
-        let result = turn(&game_state, 0.0);
+        let result = turn(&game_state, 0.0, &mut StdRng::seed_from_u64(0));
 
-        assert_eq!(result, 0);
+        assert_eq!(result, Some(0));
     }
 
     #[test]
@@ -157,13 +174,123 @@ mod test {
             4, 0, 5
         ];
         let _ex_array = [
-            0, 0, 3,
-            2, 6, 7,
+            6, 1, 3,
+            2, 0, 0,
             4, 0, 5
         ];
         let game_state = GameState::from_array(array);
-        let result = turn(&game_state, 0.0);
+        let result = turn(&game_state, 0.0, &mut StdRng::seed_from_u64(0));
+
+        assert_eq!(result, Some(0));
+    }
+
+    #[test]
+    fn ai_test_winning_4x4() {
+        let array = vec![
+            0, 0, 0, 0,
+            2, 4, 0, 0,
+            1, 0, 3, 0,
+            0, 0, 0, 5,
+        ];
+        let game_state = GameState::with_board(Board::square(4, 3), array);
+        let result = turn(&game_state, 0.0, &mut StdRng::seed_from_u64(0));
 
-        assert_eq!(result, 5);
+        assert_eq!(result, Some(6));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ai_test_perfect_blocks() {
+        let array = [
+            1, 2, 0,
+            0, 3, 0,
+            0, 0, 0
+        ];
+        let game_state = GameState::from_array(array);
+        assert_eq!(choose(&game_state, Player::X, &Level::Perfect, &mut StdRng::seed_from_u64(0)), Some(8));
+    }
+
+    #[test]
+    fn ai_test_search_blocks() {
+        let array = [
+            1, 2, 0,
+            0, 3, 0,
+            0, 0, 0
+        ];
+        let game_state = GameState::from_array(array);
+        let level = Level::Search { depth: 4, nodes: None };
+        assert_eq!(choose(&game_state, Player::X, &level, &mut StdRng::seed_from_u64(0)), Some(8));
+    }
+
+    #[test]
+    fn ai_test_mcts_wins() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5
+        ];
+        let game_state = GameState::from_array(array);
+        let level = Level::Mcts(MctsConfig::default());
+        assert_eq!(choose(&game_state, Player::X, &level, &mut StdRng::seed_from_u64(0)), Some(0));
+    }
+
+    #[test]
+    /// the same seed always picks the same moves
+    /// even with a lot of noise
+    fn ai_test_seeded() {
+        let game_state = GameState::new();
+        let first: Vec<_> = (0..10)
+        .map(|seed| turn(&game_state, 50.0, &mut StdRng::seed_from_u64(seed)))
+        .collect();
+        let second: Vec<_> = (0..10)
+        .map(|seed| turn(&game_state, 50.0, &mut StdRng::seed_from_u64(seed)))
+        .collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    /// every legal move is scored, symmetric
+    /// moves get the same score
+    fn ai_test_evaluate_moves() {
+        let scores = evaluate_moves(&GameState::new());
+        let tiles: Vec<u16> = scores.iter().map(|&(tile, _)| tile).collect();
+        assert_eq!(tiles, (0..9).collect::<Vec<u16>>());
+        assert_eq!(scores[0].1, scores[2].1);
+        assert_eq!(scores[1].1, scores[3].1);
+    }
+
+    #[test]
+    /// moves with the same score are all picked
+    /// by some seed instead of always the same one
+    fn ai_test_ties() {
+        let array = [
+            0, 0, 0,
+            0, 1, 0,
+            0, 0, 0
+        ];
+        let game_state = GameState::from_array(array);
+        let mut picked: Vec<u16> = (0..50)
+        .filter_map(|seed| turn(&game_state, 0.0, &mut StdRng::seed_from_u64(seed)))
+        .collect();
+        picked.sort_unstable();
+        picked.dedup();
+        assert_eq!(picked, vec![0, 2, 6, 8]);
+    }
+
+    #[test]
+    /// the AI only moves for the side it plays,
+    /// and finds the same win when X starts
+    fn ai_test_player() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5
+        ];
+        let game_state = GameState::from_array(array);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert_eq!(choose(&game_state, Player::O, &Level::Heuristic(0.0), &mut rng), None);
+
+        let rules = Rules::default().with_first(Player::X);
+        let game_state = GameState::with_rules(rules, array.to_vec());
+        assert_eq!(choose(&game_state, Player::O, &Level::Heuristic(0.0), &mut rng), Some(0));
+    }
+}
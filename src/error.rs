@@ -0,0 +1,106 @@
+use std::fmt;
+
+use crate::models::{Cell, Player};
+
+/// Reasons a move or a position can be rejected.
+/// Tiles are array indices
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum GameError {
+    /// the tile already holds a live mark
+    OccupiedCell(u16),
+    /// the tile holds the mark that is about
+    /// to expire, it can not be played yet
+    ExpiringCell(u16),
+    /// the tile is not on the board
+    OutOfBounds(u16),
+    /// the game has already been won by this player
+    GameWon(Cell),
+    /// there is no empty tile left to play on
+    NoLegalMoves,
+    /// it is the other player's move
+    NotYourTurn(Player),
+    /// the move numbers in the array are not
+    /// consistent with each other
+    CorruptNumbering,
+    /// the array does not have one number per tile
+    WrongLength { expected: usize, found: usize },
+    /// this move number appears on more than one tile
+    DuplicateMove(u16),
+    /// this move should still be on the board
+    /// but is missing from the array
+    MissingMove(u16),
+    /// this move should already have expired
+    StaleMove(u16),
+    /// more marks on the board than the expiry
+    /// window allows
+    TooManyMarks { count: usize, window: u16 },
+    /// a line is complete that the last move
+    /// could not have made
+    UnreachableWin,
+    /// the board has more tiles than a bitboard
+    /// can hold
+    BoardTooLarge(usize),
+    /// the last move sent the player to this
+    /// local board of an ultimate game
+    WrongBoard(u16),
+    /// this local board of an ultimate game has
+    /// been won or filled
+    BoardClosed(u16),
+    /// with gravity, the tile is above an empty
+    /// tile of its column
+    FloatingTile(u16),
+    /// with gravity, this column has no free tile
+    /// to drop a mark in
+    ColumnFull(u16),
+}
+impl GameError {
+    /// short stable identifier, passed to
+    /// javascript along with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            GameError::OccupiedCell(_) => "OCCUPIED_CELL",
+            GameError::ExpiringCell(_) => "EXPIRING_CELL",
+            GameError::OutOfBounds(_) => "OUT_OF_BOUNDS",
+            GameError::GameWon(_) => "GAME_WON",
+            GameError::NoLegalMoves => "NO_LEGAL_MOVES",
+            GameError::NotYourTurn(_) => "NOT_YOUR_TURN",
+            GameError::CorruptNumbering => "CORRUPT_NUMBERING",
+            GameError::WrongLength { .. } => "WRONG_LENGTH",
+            GameError::DuplicateMove(_) => "DUPLICATE_MOVE",
+            GameError::MissingMove(_) => "MISSING_MOVE",
+            GameError::StaleMove(_) => "STALE_MOVE",
+            GameError::TooManyMarks { .. } => "TOO_MANY_MARKS",
+            GameError::UnreachableWin => "UNREACHABLE_WIN",
+            GameError::BoardTooLarge(_) => "BOARD_TOO_LARGE",
+            GameError::WrongBoard(_) => "WRONG_BOARD",
+            GameError::BoardClosed(_) => "BOARD_CLOSED",
+            GameError::FloatingTile(_) => "FLOATING_TILE",
+            GameError::ColumnFull(_) => "COLUMN_FULL",
+        }
+    }
+}
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::OccupiedCell(tile) => write!(f, "tile {} is already occupied", tile),
+            GameError::ExpiringCell(tile) => write!(f, "tile {} holds an expiring mark", tile),
+            GameError::OutOfBounds(tile) => write!(f, "tile {} is not on the board", tile),
+            GameError::GameWon(cell) => write!(f, "the game has already been won by {:?}", cell),
+            GameError::NoLegalMoves => write!(f, "there are no legal moves left"),
+            GameError::NotYourTurn(player) => write!(f, "it is not {:?}'s turn", player),
+            GameError::CorruptNumbering => write!(f, "the move numbers of the game are inconsistent"),
+            GameError::WrongLength { expected, found } => write!(f, "expected {} tiles but got {}", expected, found),
+            GameError::DuplicateMove(n) => write!(f, "move {} appears more than once", n),
+            GameError::MissingMove(n) => write!(f, "move {} should still be on the board", n),
+            GameError::StaleMove(n) => write!(f, "move {} should have expired", n),
+            GameError::TooManyMarks { count, window } => write!(f, "{} marks on the board but only {} are kept", count, window),
+            GameError::UnreachableWin => write!(f, "a line is complete that the last move did not make"),
+            GameError::BoardTooLarge(tiles) => write!(f, "a board of {} tiles is too large for a bitboard", tiles),
+            GameError::WrongBoard(board) => write!(f, "the move must be made on board {}", board),
+            GameError::BoardClosed(board) => write!(f, "board {} is already decided", board),
+            GameError::FloatingTile(tile) => write!(f, "tile {} is above an empty tile", tile),
+            GameError::ColumnFull(column) => write!(f, "column {} has no free tile", column),
+        }
+    }
+}
+impl std::error::Error for GameError {}
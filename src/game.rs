@@ -0,0 +1,543 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use wasm_bindgen::prelude::*;
+
+use crate::ai::{self, Level};
+use crate::strategy::{self, Strategy};
+use crate::error::GameError;
+use crate::hint;
+use crate::models::{Cell, GameState, Player, Rules};
+use crate::notation::Record;
+use crate::utils::today;
+use crate::{mcts_config, unknown_strategy};
+
+/// One move of a game, with the tile of the
+/// mark it made expire if there was one
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub tile: u16,
+    pub player: Player,
+    pub expired: Option<u16>,
+}
+
+/// A game in progress, owned on the rust side
+/// so javascript only sends the tile of each
+/// move instead of the whole array.
+/// Every position of the game is kept so moves
+/// can be taken back and replayed, playing a
+/// move after an undo drops the undone moves.
+/// The AI draws its randomness from the seed of
+/// the game, so replaying the same moves with
+/// the same seed replays the whole game
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Game {
+    positions: Vec<GameState>,
+    moves: Vec<Move>,
+    ply: usize,
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Game {
+    /// starts from an existing state, the moves
+    /// that led to it are not known. The seed is
+    /// picked at random
+    pub fn from_state(state: GameState) -> Self {
+        Self::from_state_seeded(state, rand::random())
+    }
+
+    pub fn from_state_seeded(state: GameState, seed: u64) -> Self {
+        Self {
+            positions: vec![state],
+            moves: Vec::new(),
+            ply: 0,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// starts from an array after validating it,
+    /// see `GameState::try_with_rules`
+    pub fn load(rules: Rules, array: &[u16]) -> Result<Self, GameError> {
+        Ok(Self::from_state(GameState::try_with_rules(rules, array)?))
+    }
+
+    /// the current position
+    pub fn state(&self) -> &GameState {
+        &self.positions[self.ply]
+    }
+
+    /// every move played, including the ones
+    /// after the current position that can be
+    /// redone
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// the positions from the start of the game
+    /// up to and including the current one
+    pub fn positions(&self) -> impl Iterator<Item = &GameState> {
+        self.positions.iter().take(self.ply + 1)
+    }
+
+    /// plays the tile for the player to move
+    /// and returns the winner after the move
+    pub fn turn(&mut self, tile: u16) -> Result<Cell, GameError> {
+        let state = self.state();
+        let new = state.turn(tile)?;
+        let expired = state.array.iter().position(|&n| n == state.expiring && n != 0).map(|i| i as u16);
+        let played = Move { tile, player: state.to_move(), expired };
+
+        self.positions.truncate(self.ply + 1);
+        self.moves.truncate(self.ply);
+        self.positions.push(new);
+        self.moves.push(played);
+        self.ply += 1;
+        Ok(self.state().winner())
+    }
+
+    /// drops a mark in the column for the player
+    /// to move, see `Rules::drop_tile`, and
+    /// returns the winner after the move
+    pub fn drop_mark(&mut self, column: u16) -> Result<Cell, GameError> {
+        let state = self.state();
+        let tile = state.rules.drop_tile(state, column).ok_or(GameError::ColumnFull(column))?;
+        self.turn(tile)
+    }
+
+    /// lets `strategy` play for `player` and
+    /// returns the tile it played on
+    pub fn ai_turn(&mut self, player: Player, strategy: &dyn Strategy) -> Result<u16, GameError> {
+        let winner = self.state().winner();
+        if winner != Cell::N {
+            return Err(GameError::GameWon(winner));
+        }
+        if self.state().to_move() != player {
+            return Err(GameError::NotYourTurn(player));
+        }
+        let state = &self.positions[self.ply];
+        let tile = ai::choose(state, player, strategy, &mut self.rng).ok_or(GameError::NoLegalMoves)?;
+        self.turn(tile)?;
+        Ok(tile)
+    }
+}
+
+#[wasm_bindgen]
+impl Game {
+    #[wasm_bindgen(constructor)]
+    /// WASM Function
+    /// an empty game on the classic 3x3 board
+    pub fn new() -> Self {
+        Self::from_state(GameState::new())
+    }
+
+    /// WASM Function
+    /// an empty 3x3 game where `first` moves
+    /// first, 1 = X and -1 = O
+    pub fn with_first(first: i32) -> Self {
+        let rules = Rules::default().with_first(Player::from_number(first).unwrap_or_default());
+        Self::from_state(GameState::empty(rules))
+    }
+
+    /// WASM Function
+    /// an empty game with the given board and
+    /// optionally an expiry window and first player
+    pub fn sized(width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Self {
+        let rules = Rules::sized(width, height, win_length, window, per_player, first);
+        Self::from_state(GameState::empty(rules))
+    }
+
+    /// WASM Function
+    /// an empty game with any rules
+    pub fn with_rules(rules: &Rules) -> Self {
+        Self::from_state(GameState::empty(*rules))
+    }
+
+    /// WASM Function
+    /// resumes a 3x3 game from a saved array,
+    /// throws if the array is not a legal position
+    pub fn from_array(array: Vec<u16>) -> Result<Game, JsValue> {
+        Ok(Self::load(Rules::default(), &array)?)
+    }
+
+    /// WASM Function
+    pub fn from_array_sized(array: Vec<u16>, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Result<Game, JsValue> {
+        let rules = Rules::sized(width, height, win_length, window, per_player, first);
+        Ok(Self::load(rules, &array)?)
+    }
+
+    /// WASM Function
+    /// resumes a game with any rules from a saved
+    /// array
+    pub fn from_array_with(array: Vec<u16>, rules: &Rules) -> Result<Game, JsValue> {
+        Ok(Self::load(*rules, &array)?)
+    }
+
+    /// WASM Function
+    /// the rules of the game
+    pub fn rules(&self) -> Rules {
+        self.state().rules
+    }
+
+    /// WASM Function
+    /// plays a tile and returns the winner where
+    /// 1 = X, -1 = O and 0 means no one has won
+    pub fn play(&mut self, tile: u16) -> Result<i32, JsValue> {
+        Ok(self.turn(tile)?.number())
+    }
+
+    /// WASM Function
+    /// drops a mark in a column of a game with
+    /// gravity and returns the winner like `play`
+    pub fn play_column(&mut self, column: u16) -> Result<i32, JsValue> {
+        Ok(self.drop_mark(column)?.number())
+    }
+
+    /// WASM Function
+    /// lets the AI move for the player to move
+    /// and returns its tile. Calling it before
+    /// any move lets the human play second
+    pub fn ai_play(&mut self, difficulty: f32) -> Result<u16, JsValue> {
+        Ok(self.ai_turn(self.state().to_move(), &Level::Heuristic(difficulty))?)
+    }
+
+    /// WASM Function
+    /// lets the AI play a perfect move, the first
+    /// call for a ruleset solves it which can take
+    /// a moment
+    pub fn ai_play_perfect(&mut self) -> Result<u16, JsValue> {
+        Ok(self.ai_turn(self.state().to_move(), &Level::Perfect)?)
+    }
+
+    /// WASM Function
+    /// lets the search AI move, looking `depth`
+    /// moves ahead and stopping early after
+    /// `nodes` positions if given
+    pub fn ai_play_search(&mut self, depth: u8, nodes: Option<u64>) -> Result<u16, JsValue> {
+        Ok(self.ai_turn(self.state().to_move(), &Level::Search { depth, nodes })?)
+    }
+
+    /// WASM Function
+    /// lets the Monte Carlo AI move after playing
+    /// `iterations` random games, each cut off
+    /// after `max_moves` if given, throws if
+    /// `iterations` is 0
+    pub fn ai_play_mcts(&mut self, iterations: u32, max_moves: Option<u16>) -> Result<u16, JsValue> {
+        Ok(self.ai_turn(self.state().to_move(), &Level::Mcts(mcts_config(iterations, max_moves)?))?)
+    }
+
+    /// WASM Function
+    /// lets any registered strategy move, named
+    /// like "mcts:5000", throws if no strategy
+    /// has that name
+    pub fn ai_play_strategy(&mut self, name: &str) -> Result<u16, JsValue> {
+        let strategy = strategy::strategy(name).ok_or_else(|| unknown_strategy(name))?;
+        Ok(self.ai_turn(self.state().to_move(), strategy.as_ref())?)
+    }
+
+    /// WASM Function
+    /// explains every legal move for the player
+    /// to move, see `hint::hint_to_js` for the
+    /// shape of the returned object
+    pub fn hint(&self) -> JsValue {
+        hint::hint_to_js(&hint::hint(self.state()))
+    }
+
+    /// WASM Function
+    /// 1 = X, -1 = O and 0 means no one has won
+    pub fn winner(&self) -> i32 {
+        self.state().winner().number()
+    }
+
+    /// WASM Function
+    /// 1 = X won, -1 = O won, 0 means the game
+    /// goes on and 2 that it is drawn
+    pub fn status(&self) -> i32 {
+        self.state().status().number()
+    }
+
+    /// WASM Function
+    /// the tiles of every line the winner has
+    /// completed, one after the other, so every
+    /// `win_length` tiles make one line
+    pub fn winning_lines(&self) -> Vec<u16> {
+        self.state().win().map_or(Vec::new(), |win| win.lines.concat())
+    }
+
+    /// WASM Function
+    /// the owner of every tile, 1 = X, -1 = O and
+    /// 0 is empty. The expiring mark still shows
+    /// its owner, see `expiring_tile`
+    pub fn cells(&self) -> Vec<i32> {
+        self.state().array.iter().map(|&n| self.state().rules.owner(n).number()).collect()
+    }
+
+    /// WASM Function
+    /// the array of move numbers, for saving
+    pub fn array(&self) -> Vec<u16> {
+        self.state().array.clone()
+    }
+
+    /// WASM Function
+    /// the tile of the mark that is removed on
+    /// the next move, if any
+    pub fn expiring_tile(&self) -> Option<u16> {
+        if self.state().expiring == 0 {
+            return None;
+        }
+        self.state().array.iter().position(|&n| n == self.state().expiring).map(|i| i as u16)
+    }
+
+    /// WASM Function
+    /// 1 = X, -1 = O
+    pub fn current_player(&self) -> i32 {
+        self.state().to_move().number()
+    }
+
+    /// WASM Function
+    /// the seed the AI of this game plays with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// WASM Function
+    /// restarts the AI's randomness from a seed,
+    /// call it right after creating a game to
+    /// replay a game that used that seed
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// WASM Function
+    /// the tiles played in this game in order,
+    /// including undone moves that can be redone
+    pub fn history(&self) -> Vec<u16> {
+        self.moves.iter().map(|played| played.tile).collect()
+    }
+
+    /// WASM Function
+    /// for every move of `history` the tile of the
+    /// mark it made expire, or -1 if none did
+    pub fn expired_tiles(&self) -> Vec<i32> {
+        self.moves.iter().map(|played| played.expired.map_or(-1, i32::from)).collect()
+    }
+
+    /// WASM Function
+    /// the number of moves played up to the
+    /// current position
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    /// WASM Function
+    /// takes back the last move, false if there
+    /// is nothing to undo
+    pub fn undo(&mut self) -> bool {
+        if self.ply == 0 {
+            return false;
+        }
+        self.ply -= 1;
+        true
+    }
+
+    /// WASM Function
+    /// plays the last undone move again, false
+    /// if there is nothing to redo
+    pub fn redo(&mut self) -> bool {
+        if self.ply == self.moves.len() {
+            return false;
+        }
+        self.ply += 1;
+        true
+    }
+
+    /// WASM Function
+    /// moves to the position after `ply` moves,
+    /// false if the game is not that long
+    pub fn jump_to(&mut self, ply: usize) -> bool {
+        if ply > self.moves.len() {
+            return false;
+        }
+        self.ply = ply;
+        true
+    }
+
+    /// WASM Function
+    /// the array of the position after `ply`
+    /// moves, for the move list
+    pub fn array_at(&self, ply: usize) -> Option<Vec<u16>> {
+        self.positions.get(ply).map(|state| state.array.clone())
+    }
+
+    /// WASM Function
+    /// the game up to the current position in the
+    /// text notation of `notation::Record`. The
+    /// date defaults to today
+    pub fn to_record(&self, x: Option<String>, o: Option<String>, date: Option<String>) -> String {
+        let date = date.unwrap_or_else(today);
+        let record = Record { x, o, date: Some(date), ..Record::from_game(self) };
+        record.to_string()
+    }
+
+    /// WASM Function
+    /// reads a game written by `to_record`, with
+    /// every move in its history
+    pub fn from_record(text: &str) -> Result<Game, JsValue> {
+        Ok(Record::parse(text)?.to_game()?)
+    }
+
+    /// WASM Function
+    /// the current position in the compact
+    /// notation of `GameState::to_notation`
+    pub fn position(&self) -> String {
+        self.state().to_notation()
+    }
+
+    /// WASM Function
+    /// a game starting from a position written
+    /// by `position`, with the rules of `sized`
+    pub fn from_position(text: &str, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Result<Game, JsValue> {
+        Game::from_position_with(text, &Rules::sized(width, height, win_length, window, per_player, first))
+    }
+
+    /// WASM Function
+    /// the same as `from_position` with any rules,
+    /// like classic, cubic or gravity games
+    pub fn from_position_with(text: &str, rules: &Rules) -> Result<Game, JsValue> {
+        Ok(Game::from_state(GameState::from_notation(*rules, text)?))
+    }
+}
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::{Game, Move};
+    use crate::ai::Level;
+    use crate::error::GameError;
+    use crate::models::{Cell, Player, Rules};
+    use crate::utils::today;
+
+    #[test]
+    fn game_play() {
+        let mut game = Game::new();
+        assert_eq!(game.current_player(), -1);
+
+        for tile in [4, 0, 8, 2, 1, 7] {
+            assert_eq!(game.turn(tile), Ok(Cell::N));
+        }
+        assert_eq!(game.current_player(), -1);
+        assert_eq!(game.expiring_tile(), Some(4));
+        assert_eq!(game.cells(), vec![1, -1, 1, 0, -1, 0, 0, 1, -1]);
+        assert_eq!(game.history(), vec![4, 0, 8, 2, 1, 7]);
+        assert_eq!(game.turn(4), Err(GameError::ExpiringCell(4)));
+    }
+
+    #[test]
+    fn game_winner() {
+        let array = [
+            2, 1, 0,
+            4, 3, 0,
+            0, 0, 0,
+        ];
+        let mut game = Game::load(Rules::default(), &array).unwrap();
+        assert_eq!(game.turn(8), Ok(Cell::N));
+        assert_eq!(game.turn(6), Ok(Cell::X));
+        assert_eq!(game.winner(), 1);
+        assert_eq!(game.winning_lines(), vec![0, 3, 6]);
+        assert_eq!(game.ai_turn(Player::O, &Level::Heuristic(0.0)), Err(GameError::GameWon(Cell::X)));
+    }
+
+    #[test]
+    fn game_ai_play() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let mut game = Game::load(Rules::default(), &array).unwrap();
+        assert_eq!(game.ai_turn(Player::O, &Level::Heuristic(0.0)), Err(GameError::NotYourTurn(Player::O)));
+        assert_eq!(game.ai_turn(Player::X, &Level::Heuristic(0.0)), Ok(0));
+        assert_eq!(game.winner(), 1);
+        assert_eq!(game.history(), vec![0]);
+    }
+
+    #[test]
+    /// a noisy AI plays the same game again
+    /// when the seed and the human moves match
+    fn game_replay_seed() {
+        let play = |game: &mut Game| {
+            for tile in [4, 0, 8] {
+                if game.turn(tile).is_err() {
+                    continue;
+                }
+                if game.ai_turn(Player::X, &Level::Heuristic(20.0)).is_err() {
+                    break;
+                }
+            }
+            game.history()
+        };
+
+        let mut game = Game::new();
+        let history = play(&mut game);
+
+        let mut replay = Game::new();
+        replay.reseed(game.seed());
+        assert_eq!(play(&mut replay), history);
+    }
+
+    #[test]
+    /// the record is dated today unless a date
+    /// is given, also outside the browser
+    fn game_to_record() {
+        let mut game = Game::new();
+        game.turn(4).unwrap();
+        let text = game.to_record(Some("Alice".to_string()), None, None);
+        assert!(text.contains(&format!("[Date \"{}\"]", today())));
+        assert!(text.contains("[X \"Alice\"]"));
+        let text = game.to_record(None, None, Some("2024-05-01".to_string()));
+        assert!(text.contains("[Date \"2024-05-01\"]"));
+    }
+
+    #[test]
+    /// every move keeps the tile it made expire
+    /// and undo, redo and jump move through them
+    fn game_undo_redo() {
+        let mut game = Game::new();
+        for tile in [4, 0, 8, 2, 1, 7, 6] {
+            game.turn(tile).unwrap();
+        }
+        assert_eq!(game.moves()[6], Move { tile: 6, player: Player::O, expired: Some(4) });
+        assert_eq!(game.expired_tiles(), vec![-1, -1, -1, -1, -1, -1, 4]);
+
+        assert!(game.undo());
+        assert_eq!(game.ply(), 6);
+        assert_eq!(game.expiring_tile(), Some(4));
+        assert!(game.redo());
+        assert!(!game.redo());
+        assert_eq!(game.array()[4], 0);
+
+        assert!(game.jump_to(2));
+        assert_eq!(game.array(), vec![2, 0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(game.positions().count(), 3);
+        assert!(!game.jump_to(8));
+
+        // a new move replaces the undone ones
+        game.turn(5).unwrap();
+        assert_eq!(game.history(), vec![4, 0, 5]);
+        assert!(!game.redo());
+        assert!(game.jump_to(0));
+        assert!(!game.undo());
+    }
+}
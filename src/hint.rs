@@ -0,0 +1,243 @@
+use wasm_bindgen::prelude::*;
+
+use crate::ai;
+use crate::models::{Cell, GameState};
+
+/// Why a move is worth considering
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Reason {
+    /// the move wins the game
+    CompletesLine,
+    /// the opponent would win on this tile
+    /// with their next move
+    BlocksLine,
+    /// the move makes two or more lines that
+    /// are one mark short of winning
+    CreatesFork,
+    /// the move would complete a line with the
+    /// player's mark on this tile, but that mark
+    /// expires when the move is made
+    MarkExpires(u16),
+}
+impl Reason {
+    /// short stable identifier for javascript
+    pub fn code(&self) -> &'static str {
+        match self {
+            Reason::CompletesLine => "COMPLETES_LINE",
+            Reason::BlocksLine => "BLOCKS_LINE",
+            Reason::CreatesFork => "CREATES_FORK",
+            Reason::MarkExpires(_) => "MARK_EXPIRES",
+        }
+    }
+}
+
+/// One legal move with its heuristic score,
+/// see `ai::evaluate_moves`
+#[derive(Clone, PartialEq, Debug)]
+pub struct MoveHint {
+    pub tile: u16,
+    pub score: f32,
+    pub reasons: Vec<Reason>,
+}
+
+/// Every legal move of a position explained,
+/// and the move the AI would play without noise
+#[derive(Clone, PartialEq, Debug)]
+pub struct Hint {
+    pub moves: Vec<MoveHint>,
+    pub recommended: Option<u16>,
+}
+
+/// Explains every legal move for the player to
+/// move. The recommended move is the best
+/// scored one, the lowest tile on ties
+pub fn hint(game_state: &GameState) -> Hint {
+    let player = game_state.to_move().cell();
+
+    let mut recommended: Option<(u16, f32)> = None;
+    let moves: Vec<MoveHint> = ai::evaluate_moves(game_state)
+    .into_iter()
+    .map(|(tile, score)| {
+        if recommended.map_or(true, |(_, best)| score > best) {
+            recommended = Some((tile, score));
+        }
+        MoveHint { tile, score, reasons: reasons(game_state, tile, player) }
+    })
+    .collect();
+
+    Hint { moves, recommended: recommended.map(|(tile, _)| tile) }
+}
+
+/// private function. The reasons a move by
+/// `player` on `tile` is interesting
+fn reasons(game_state: &GameState, tile: u16, player: Cell) -> Vec<Reason> {
+    let new = match game_state.turn(tile) {
+        Ok(new) => new,
+        Err(_) => return Vec::new(),
+    };
+    let opponent = if player == Cell::X { Cell::O } else { Cell::X };
+    let possibilities = game_state.board().lines();
+    let mut reasons = Vec::new();
+
+    let won = new.winner() == player;
+    if won {
+        reasons.push(Reason::CompletesLine);
+    }
+
+    // the opponent still has the rest of the
+    // line after this move, so they would win here
+    let blocks = possibilities.iter().any(|line| {
+        line.contains(&tile) && line.iter().all(|&i| i == tile || new.get(i) == opponent)
+    });
+    if blocks {
+        reasons.push(Reason::BlocksLine);
+    }
+
+    let mut threats: Vec<u16> = possibilities
+    .iter()
+    .filter(|line| line.contains(&tile))
+    .filter_map(|line| {
+        let empty: Vec<u16> = line.iter().copied().filter(|&i| new.get(i) == Cell::N).collect();
+        let own = line.iter().filter(|&&i| new.get(i) == player).count();
+        match empty[..] {
+            [i] if own == line.len() - 1 => Some(i),
+            _ => None,
+        }
+    })
+    .collect();
+    threats.sort_unstable();
+    threats.dedup();
+    if !won && threats.len() >= 2 {
+        reasons.push(Reason::CreatesFork);
+    }
+
+    // the expiring mark belongs to the player to
+    // move and is gone as soon as they play
+    let expiring = game_state.array.iter().position(|&n| n == game_state.expiring && n != 0);
+    if let Some(expiring) = expiring.map(|i| i as u16) {
+        let owner = game_state.rules.owner(game_state.expiring);
+        let misleading = owner == player && possibilities.iter().any(|line| {
+            line.contains(&tile) && line.contains(&expiring)
+                && line.iter().all(|&i| i == tile || i == expiring || game_state.get(i) == player)
+        });
+        if misleading {
+            reasons.push(Reason::MarkExpires(expiring));
+        }
+    }
+
+    reasons
+}
+
+/// the hint as a javascript object of the form
+/// `{ recommended, moves: [{ tile, score, reasons: [{ code, tile }] }] }`
+/// where `recommended` is undefined when there
+/// is no legal move and `tile` of a reason is
+/// only set for `MARK_EXPIRES`
+pub(crate) fn hint_to_js(hint: &Hint) -> JsValue {
+    let set = |object: &js_sys::Object, key: &str, value: &JsValue| {
+        // setting a property on a plain object can not fail
+        let _ = js_sys::Reflect::set(object, &key.into(), value);
+    };
+
+    let moves = js_sys::Array::new();
+    for move_hint in &hint.moves {
+        let reasons = js_sys::Array::new();
+        for reason in &move_hint.reasons {
+            let object = js_sys::Object::new();
+            set(&object, "code", &reason.code().into());
+            if let Reason::MarkExpires(tile) = reason {
+                set(&object, "tile", &(*tile).into());
+            }
+            reasons.push(&object);
+        }
+
+        let object = js_sys::Object::new();
+        set(&object, "tile", &move_hint.tile.into());
+        set(&object, "score", &move_hint.score.into());
+        set(&object, "reasons", &reasons);
+        moves.push(&object);
+    }
+
+    let object = js_sys::Object::new();
+    if let Some(tile) = hint.recommended {
+        set(&object, "recommended", &tile.into());
+    }
+    set(&object, "moves", &moves);
+    object.into()
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::{hint, Reason};
+    use crate::models::GameState;
+
+    fn reasons_for(game_state: &GameState, tile: u16) -> Vec<Reason> {
+        hint(game_state)
+        .moves
+        .into_iter()
+        .find(|move_hint| move_hint.tile == tile)
+        .unwrap()
+        .reasons
+    }
+
+    #[test]
+    fn hint_completes_line() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let game_state = GameState::from_array(array);
+        let result = hint(&game_state);
+
+        assert_eq!(result.recommended, Some(0));
+        assert_eq!(result.moves.len(), 4);
+        assert_eq!(reasons_for(&game_state, 0), vec![Reason::CompletesLine]);
+    }
+
+    #[test]
+    fn hint_blocks_line() {
+        let array = [
+            1, 2, 0,
+            0, 3, 0,
+            0, 0, 0,
+        ];
+        let game_state = GameState::from_array(array);
+
+        assert_eq!(hint(&game_state).recommended, Some(8));
+        assert_eq!(reasons_for(&game_state, 8), vec![Reason::BlocksLine]);
+        assert_eq!(reasons_for(&game_state, 5), vec![]);
+    }
+
+    #[test]
+    fn hint_creates_fork() {
+        let array = [
+            1, 2, 0,
+            0, 0, 4,
+            3, 0, 0,
+        ];
+        let game_state = GameState::from_array(array);
+        assert!(reasons_for(&game_state, 8).contains(&Reason::CreatesFork));
+    }
+
+    #[test]
+    /// O's mark on tile 0 is expiring, so tile 8
+    /// does not complete the diagonal
+    fn hint_mark_expires() {
+        let array = [
+            1, 2, 0,
+            6, 5, 0,
+            4, 0, 0,
+        ];
+        let game_state = GameState::from_array(array);
+        assert_eq!(reasons_for(&game_state, 8), vec![Reason::MarkExpires(0)]);
+    }
+}
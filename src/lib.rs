@@ -1,13 +1,29 @@
 
-/// build command: wasm-pack build --out-dir tictactoe_node\wasm
+//! build command: wasm-pack build --out-dir tictactoe_node\wasm
 
 pub mod utils;
 pub mod models;
+pub mod error;
 pub mod ai;
+pub mod game;
+pub mod solver;
+pub mod hint;
+pub mod notation;
+pub mod position;
+pub mod search;
+pub mod mcts;
+pub mod symmetry;
+pub mod strategy;
+pub mod ultimate;
 
-use utils::{get_possibilities, state_vec_to_array};
 use wasm_bindgen::prelude::*;
 use models::*;
+use ai::Level;
+use strategy::Strategy;
+use mcts::MctsConfig;
+use error::GameError;
+use game::Game;
+use notation::NotationError;
 
 #[wasm_bindgen]
 extern "C" {
@@ -21,92 +37,331 @@ extern "C" {
 /// the result where 1 = X, -1 = O and 
 /// 0 means no one has won
 pub fn check_winner(array: Vec<u16>) -> i32 {
-    let array = state_vec_to_array(array);
-    let game_state = GameState::from_array(array);
-    let possibilities = get_possibilities();
+    check_winner_on(Rules::default(), array)
+}
 
-    let mut winner = Cell::N;
+#[wasm_bindgen]
+/// WASM Function
+/// same as `check_winner` for a board of
+/// any size and win length, optionally with
+/// an expiry window, see `Rules::sized`
+pub fn check_winner_sized(array: Vec<u16>, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> i32 {
+    check_winner_on(Rules::sized(width, height, win_length, window, per_player, first), array)
+}
 
-    'a:for possibility in possibilities {
-        let cell = game_state.get(possibility[0]);
-        if cell == Cell::N || cell == Cell::E {
-            continue;
-        }
-        for i in possibility {
-            if game_state.get(i) != cell {
-                continue 'a;
-            }
-        }
+#[wasm_bindgen]
+/// WASM Function
+/// same as `check_winner` with any rules
+pub fn check_winner_with(array: Vec<u16>, rules: &Rules) -> i32 {
+    check_winner_on(*rules, array)
+}
 
-        winner = cell;
-        break;
-    }
+#[wasm_bindgen]
+/// WASM Function
+/// the status of a 3x3 game: 1 = X won, -1 = O
+/// won, 0 means the game goes on and 2 that it
+/// is drawn, which only happens when marks
+/// never expire
+pub fn game_status(array: Vec<u16>) -> i32 {
+    game_status_with(array, &Rules::default())
+}
+
+#[wasm_bindgen]
+/// WASM Function
+/// same as `game_status` with any rules
+pub fn game_status_with(array: Vec<u16>, rules: &Rules) -> i32 {
+    GameState::with_rules(*rules, array).status().number()
+}
 
-    winner.number()
+/// checks the game array with the given rules
+/// for a winner, see `check_winner`
+pub fn check_winner_on(rules: Rules, array: Vec<u16>) -> i32 {
+    GameState::with_rules(rules, array).winner().number()
 }
 
 #[wasm_bindgen]
 /// WASM Function
-pub fn make_move_wrapper(array: Vec<u16>, tile: u16) -> Option<Vec<i32>> {
-    let array = state_vec_to_array(array);
-    match make_move(array, tile) {
-    Some((arr, winner)) => {
-        let mut vec: Vec<i32> = arr.iter().map(|&n| n as i32).collect();
-        vec.push(winner);
-        Some(vec)
-    },
-    None => None,
-    }
+/// the tiles of the lines the winner has
+/// completed, every 3 tiles make one line.
+/// Empty if no one has won
+pub fn winning_lines(array: Vec<u16>) -> Vec<u16> {
+    winning_lines_sized(array, 3, 3, 3, None, None, None)
+}
+
+#[wasm_bindgen]
+/// WASM Function
+/// every `win_length` tiles make one line
+pub fn winning_lines_sized(array: Vec<u16>, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Vec<u16> {
+    winning_lines_with(array, &Rules::sized(width, height, win_length, window, per_player, first))
+}
+
+#[wasm_bindgen]
+/// WASM Function
+pub fn winning_lines_with(array: Vec<u16>, rules: &Rules) -> Vec<u16> {
+    GameState::with_rules(*rules, array).win().map_or(Vec::new(), |win| win.lines.concat())
+}
+
+#[wasm_bindgen]
+/// WASM Function
+/// explains every legal move of a 3x3 game,
+/// see `hint::hint_to_js` for the shape of the
+/// returned object
+pub fn hint_wrapper(array: Vec<u16>) -> Result<JsValue, JsValue> {
+    hint_with(array, &Rules::default())
+}
+
+#[wasm_bindgen]
+/// WASM Function
+/// same as `hint_wrapper` with any rules
+pub fn hint_with(array: Vec<u16>, rules: &Rules) -> Result<JsValue, JsValue> {
+    let game_state = GameState::try_with_rules(*rules, &array)?;
+    Ok(hint::hint_to_js(&hint::hint(&game_state)))
+}
+
+#[wasm_bindgen]
+/// WASM Function
+/// throws an error describing what is wrong
+/// if the array is not a legal position, for
+/// example a corrupted save
+pub fn validate_array(array: Vec<u16>) -> Result<(), JsValue> {
+    validate_array_sized(array, 3, 3, 3, None, None, None)
+}
+
+#[wasm_bindgen]
+/// WASM Function
+pub fn validate_array_sized(array: Vec<u16>, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Result<(), JsValue> {
+    validate_array_with(array, &Rules::sized(width, height, win_length, window, per_player, first))
+}
+
+#[wasm_bindgen]
+/// WASM Function
+pub fn validate_array_with(array: Vec<u16>, rules: &Rules) -> Result<(), JsValue> {
+    GameState::try_with_rules(*rules, &array)?;
+    Ok(())
+}
+
+#[wasm_bindgen]
+/// WASM Function
+pub fn make_move_wrapper(array: Vec<u16>, tile: u16) -> Result<Vec<i32>, JsValue> {
+    make_move_wrapper_sized(array, tile, 3, 3, 3, None, None, None)
+}
+
+#[wasm_bindgen]
+/// WASM Function
+#[allow(clippy::too_many_arguments)]
+pub fn make_move_wrapper_sized(array: Vec<u16>, tile: u16, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Result<Vec<i32>, JsValue> {
+    make_move_with(array, tile, &Rules::sized(width, height, win_length, window, per_player, first))
 }
+
+#[wasm_bindgen]
+/// WASM Function
+pub fn make_move_with(array: Vec<u16>, tile: u16, rules: &Rules) -> Result<Vec<i32>, JsValue> {
+    let (new, winner) = make_move(*rules, array, tile)?;
+
+    Ok(result_to_vec(new, winner))
+}
+
+#[wasm_bindgen]
+/// WASM Function
+/// drops a mark in a column with gravity rules,
+/// returned like `make_move_with`
+pub fn make_drop_with(array: Vec<u16>, column: u16, rules: &Rules) -> Result<Vec<i32>, JsValue> {
+    let mut game = Game::load(*rules, &array)?;
+    let winner = game.drop_mark(column)?;
+
+    Ok(result_to_vec(game.array(), winner.number()))
+}
+
 /// Takes a game array and a turn request
-/// the 
-pub fn make_move(array: [u16; 9], tile: u16) -> Option<([u16; 9], i32)> {
-    let game_state = GameState::from_array(array);
+/// and returns the new array along with
+/// the winner after the turn. The array is
+/// validated first, see `GameState::validate`
+pub fn make_move(rules: Rules, array: Vec<u16>, tile: u16) -> Result<(Vec<u16>, i32), GameError> {
+    let mut game = Game::load(rules, &array)?;
+    let winner = game.turn(tile)?;
 
-    match game_state.turn(tile) {
-        Some(gs) => {
-            let winner = check_winner(gs.array.to_vec());
-            
-            Some((gs.array, winner))
-        },
-        None => {
-            None
-        },
-    }
+    Ok((game.array(), winner.number()))
 }
 
 #[wasm_bindgen]
 ///WASM Function
-pub fn ai_turn_wrapper(array: Vec<u16>, tile: u16, difficulty: f32) -> Option<Vec<i32>> {
-    let array = state_vec_to_array(array);
-    let (new, winner) = match ai_turn(array, tile, difficulty) {
-    Some(tuple) => tuple,
-    None => return None,
-    };
-
-    let vec = new.to_vec();
-    let mut vec: Vec<i32> = vec.iter().map(|&n| n as i32).collect();
-    vec.push(winner);
-    Some(vec)
+/// the AI's randomness is seeded with `seed`
+/// when it is given. Without a tile the AI
+/// makes the first move of the game
+pub fn ai_turn_wrapper(array: Vec<u16>, tile: Option<u16>, difficulty: f32, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    ai_turn_wrapper_sized(array, tile, difficulty, 3, 3, 3, None, None, None, seed)
+}
+
+#[wasm_bindgen]
+///WASM Function
+#[allow(clippy::too_many_arguments)]
+pub fn ai_turn_wrapper_sized(array: Vec<u16>, tile: Option<u16>, difficulty: f32, width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    let rules = Rules::sized(width, height, win_length, window, per_player, first);
+    let state = GameState::try_with_rules(rules, &array)?;
+    // the AI plays whoever moves after the tile
+    let ai = if tile.is_some() { state.to_move().other() } else { state.to_move() };
+    let (new, winner) = ai_turn(rules, array, tile, ai, &Level::Heuristic(difficulty), seed)?;
+
+    Ok(result_to_vec(new, winner))
+}
+
+#[wasm_bindgen]
+///WASM Function
+/// the same as `ai_turn_wrapper` with the search
+/// AI, looking `depth` moves ahead and stopping
+/// early after `nodes` positions if given
+pub fn ai_search_wrapper(array: Vec<u16>, tile: Option<u16>, depth: u8, nodes: Option<u64>, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    ai_search_with(array, tile, depth, nodes, &Rules::default(), seed)
+}
+
+#[wasm_bindgen]
+///WASM Function
+/// the same as `ai_search_wrapper` with any
+/// rules
+pub fn ai_search_with(array: Vec<u16>, tile: Option<u16>, depth: u8, nodes: Option<u64>, rules: &Rules, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    let state = GameState::try_with_rules(*rules, &array)?;
+    let ai = if tile.is_some() { state.to_move().other() } else { state.to_move() };
+    let (new, winner) = ai_turn(*rules, array, tile, ai, &Level::Search { depth, nodes }, seed)?;
+
+    Ok(result_to_vec(new, winner))
+}
+
+#[wasm_bindgen]
+///WASM Function
+/// the same as `ai_turn_wrapper` with the Monte
+/// Carlo AI playing `iterations` random games,
+/// each cut off after `max_moves` if given.
+/// Throws if `iterations` is 0
+pub fn ai_mcts_wrapper(array: Vec<u16>, tile: Option<u16>, iterations: u32, max_moves: Option<u16>, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    ai_mcts_with(array, tile, iterations, max_moves, &Rules::default(), seed)
+}
+
+#[wasm_bindgen]
+///WASM Function
+/// the same as `ai_mcts_wrapper` with any
+/// rules
+pub fn ai_mcts_with(array: Vec<u16>, tile: Option<u16>, iterations: u32, max_moves: Option<u16>, rules: &Rules, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    let state = GameState::try_with_rules(*rules, &array)?;
+    let ai = if tile.is_some() { state.to_move().other() } else { state.to_move() };
+    let (new, winner) = ai_turn(*rules, array, tile, ai, &Level::Mcts(mcts_config(iterations, max_moves)?), seed)?;
+
+    Ok(result_to_vec(new, winner))
 }
-/// Makes a turn and then lets the AI make
-/// the next turn and returns the result
-pub fn ai_turn(array: [u16; 9], tile: u16, difficulty: f32) -> Option<([u16; 9], i32)> {
-    match make_move(array, tile) {
-        Some((array, result)) => {
-            if result != 0 {
-                Some((array, result))
-            } else {
-                let game_state = GameState::from_array(array);
-                let next = ai::turn(&game_state, difficulty);
-                //print_values(&next.1);
-                make_move(array, next)
-            }
-        },
-        None => {
-            None
-        },
+
+#[wasm_bindgen]
+///WASM Function
+/// the same as `ai_turn_wrapper` with any
+/// registered strategy, named like "search:6",
+/// throws if no strategy has that name
+pub fn ai_strategy_wrapper(array: Vec<u16>, tile: Option<u16>, name: &str, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    ai_turn_with(array, tile, name, &Rules::default(), seed)
+}
+
+#[wasm_bindgen]
+///WASM Function
+/// the same as `ai_strategy_wrapper` with any
+/// rules
+pub fn ai_turn_with(array: Vec<u16>, tile: Option<u16>, name: &str, rules: &Rules, seed: Option<u64>) -> Result<Vec<i32>, JsValue> {
+    let strategy = strategy::strategy(name).ok_or_else(|| unknown_strategy(name))?;
+    let state = GameState::try_with_rules(*rules, &array)?;
+    let ai = if tile.is_some() { state.to_move().other() } else { state.to_move() };
+    let (new, winner) = ai_turn(*rules, array, tile, ai, strategy.as_ref(), seed)?;
+
+    Ok(result_to_vec(new, winner))
+}
+
+#[wasm_bindgen]
+///WASM Function
+/// the names of the registered strategies
+pub fn strategy_names() -> Vec<JsValue> {
+    strategy::names().into_iter().map(JsValue::from).collect()
+}
+
+/// Makes the human's turn if a tile is given
+/// and then lets `strategy` play for `ai` if
+/// it is its move, and returns the result.
+/// Without a tile the AI can open the game.
+/// A seed makes the AI's choice reproducible
+pub fn ai_turn(rules: Rules, array: Vec<u16>, tile: Option<u16>, ai: Player, strategy: &dyn Strategy, seed: Option<u64>) -> Result<(Vec<u16>, i32), GameError> {
+    let mut game = Game::load(rules, &array)?;
+    if let Some(seed) = seed {
+        game.reseed(seed);
+    }
+    if let Some(tile) = tile {
+        if game.state().to_move() == ai {
+            return Err(GameError::NotYourTurn(ai.other()));
+        }
+        game.turn(tile)?;
+    }
+    if game.state().winner() == Cell::N {
+        game.ai_turn(ai, strategy)?;
     }
+
+    Ok((game.array(), game.winner()))
+}
+
+/// the Monte Carlo settings from the numbers
+/// passed by javascript, the rest are defaults.
+/// A search of no iterations finds no move, so
+/// it is thrown as an error
+pub(crate) fn mcts_config(iterations: u32, max_moves: Option<u16>) -> Result<MctsConfig, JsValue> {
+    if iterations == 0 {
+        return Err(bad_setting("iterations", &iterations.to_string()));
+    }
+    let config = MctsConfig { iterations, ..MctsConfig::default() };
+    Ok(MctsConfig { max_moves: max_moves.unwrap_or(config.max_moves), ..config })
+}
+
+/// Errors are thrown in javascript as an `Error`
+/// with the message and an extra `code` property
+impl From<GameError> for JsValue {
+    fn from(error: GameError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name("GameError");
+        // setting a property on a fresh Error object can not fail
+        let _ = js_sys::Reflect::set(&js_error, &"code".into(), &error.code().into());
+        js_error.into()
+    }
+}
+
+/// the same as a `GameError` but named
+/// "NotationError"
+impl From<NotationError> for JsValue {
+    fn from(error: NotationError) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name("NotationError");
+        // setting a property on a fresh Error object can not fail
+        let _ = js_sys::Reflect::set(&js_error, &"code".into(), &error.code().into());
+        js_error.into()
+    }
+}
+
+/// the error thrown for a strategy name that
+/// is not registered, named "StrategyError"
+pub(crate) fn unknown_strategy(name: &str) -> JsValue {
+    let js_error = js_sys::Error::new(&format!("no strategy is named {:?}", name));
+    js_error.set_name("StrategyError");
+    // setting a property on a fresh Error object can not fail
+    let _ = js_sys::Reflect::set(&js_error, &"code".into(), &"UNKNOWN_STRATEGY".into());
+    js_error.into()
+}
+
+/// the error thrown for a strategy setting that
+/// is out of range, named "StrategyError"
+pub(crate) fn bad_setting(key: &str, value: &str) -> JsValue {
+    let js_error = js_sys::Error::new(&format!("{:?} is not a valid {}", value, key));
+    js_error.set_name("StrategyError");
+    // setting a property on a fresh Error object can not fail
+    let _ = js_sys::Reflect::set(&js_error, &"code".into(), &"BAD_VALUE".into());
+    js_error.into()
+}
+
+/// the array passed back to javascript, with
+/// the winner appended as the last element
+fn result_to_vec(array: Vec<u16>, winner: i32) -> Vec<i32> {
+    let mut vec: Vec<i32> = array.iter().map(|&n| n as i32).collect();
+    vec.push(winner);
+    vec
 }
 
 
@@ -117,7 +372,7 @@ pub fn ai_turn(array: [u16; 9], tile: u16, difficulty: f32) -> Option<([u16; 9],
 
 #[cfg(test)]
 mod test {
-    use crate::{check_winner, make_move};
+    use crate::{ai_mcts_with, ai_search_with, ai_turn, check_winner, check_winner_sized, check_winner_with, game_status, game_status_with, make_drop_with, make_move, winning_lines, winning_lines_with, ai::Level, game::Game, models::{Player, Rules}, error::GameError};
 
     #[test]
     fn verify_check_winner_x() {
@@ -141,6 +396,16 @@ mod test {
         assert_eq!(result, -1);
     }
 
+    #[test]
+    fn verify_winning_lines() {
+        let array = vec![
+            0, 15, 0,
+            13, 11, 0,
+            12, 16, 14,
+        ];
+        assert_eq!(winning_lines(array), vec![6, 7, 8]);
+    }
+
     #[test]
     fn verify_check_winner_none() {
         let array = vec![
@@ -165,8 +430,27 @@ mod test {
             13, 0, 14,
             12, 16, 17
         ];
-        let result = make_move(array, 8);
-        assert_eq!(result, Some((expected, 0)));
+        let result = make_move(Rules::default(), array.to_vec(), 8);
+        assert_eq!(result, Ok((expected.to_vec(), 0)));
+    }
+
+    #[test]
+    /// arrays from games that went on for a very
+    /// long time come back renumbered, in the same
+    /// format and with the same marks
+    fn verify_turn_renumbered() {
+        let array = [
+            0, 65533, 0,
+            65531, 65529, 65532,
+            65530, 65534, 0,
+        ];
+        let expected = [
+            0, 5, 0,
+            3, 0, 4,
+            2, 6, 7,
+        ];
+        let result = make_move(Rules::default(), array.to_vec(), 8);
+        assert_eq!(result, Ok((expected.to_vec(), 0)));
     }
 
     #[test]
@@ -176,8 +460,129 @@ mod test {
             13, 11, 14,
             12, 16, 0,
             ];
-            let expected = None;
-        let result = make_move(array, 1);
+            let expected = Err(GameError::OccupiedCell(1));
+        let result = make_move(Rules::default(), array.to_vec(), 1);
         assert_eq!(result, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn verify_turn_corrupt() {
+        let array = [
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 16,
+            ];
+        let result = make_move(Rules::default(), array.to_vec(), 0);
+        assert_eq!(result, Err(GameError::DuplicateMove(16)));
+
+        let result = make_move(Rules::default(), vec![0; 8], 0);
+        assert_eq!(result, Err(GameError::WrongLength { expected: 9, found: 8 }));
+    }
+
+    #[test]
+    fn verify_check_winner_sized() {
+        let array = vec![
+            0, 0, 0, 0,
+            2, 4, 6, 0,
+            1, 3, 0, 0,
+            0, 0, 5, 7,
+        ];
+        assert_eq!(check_winner_sized(array.clone(), 4, 4, 3, None, None, None), 1);
+        assert_eq!(check_winner_sized(array.clone(), 4, 4, 4, None, None, None), 0);
+        // with three marks each, X's 2 is expiring
+        assert_eq!(check_winner_sized(array, 4, 4, 3, Some(3), Some(true), None), 0);
+    }
+
+    #[test]
+    fn verify_game_status() {
+        assert_eq!(game_status(vec![1, 3, 5, 2, 4, 0, 0, 0, 0]), -1);
+        assert_eq!(game_status(vec![0, 1, 0, 0, 0, 0, 0, 0, 0]), 0);
+        let draw = vec![2, 8, 3, 5, 1, 6, 4, 7, 9];
+        assert_eq!(game_status_with(draw.clone(), &Rules::classic()), 2);
+        assert_eq!(check_winner_with(draw, &Rules::classic()), 0);
+    }
+
+    #[test]
+    /// the wrappers taking rules agree with the
+    /// ones taking the size
+    fn verify_rules_wrappers() {
+        let rules = Rules::sized(4, 4, 3, Some(3), Some(true), None);
+        let array = vec![
+            0, 0, 0, 0,
+            2, 4, 6, 0,
+            1, 3, 0, 0,
+            0, 0, 5, 7,
+        ];
+        assert_eq!(check_winner_with(array.clone(), &rules), 0);
+        assert_eq!(winning_lines_with(array.clone(), &Rules::sized(4, 4, 3, None, None, None)), vec![4, 5, 6]);
+
+        let (new, winner) = make_move(rules, vec![0; 16], 5).unwrap();
+        assert_eq!(new[5], 1);
+        assert_eq!(winner, 0);
+    }
+
+    #[test]
+    /// the search and Monte Carlo wrappers play
+    /// by the rules they are given
+    fn verify_ai_with_rules() {
+        let rules = Rules::classic();
+        // X to move wins at 0, with classic rules
+        // O's first mark stays on the board
+        let array = vec![
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let new = ai_search_with(array.clone(), None, 4, None, &rules, Some(0)).unwrap();
+        assert_eq!((new[0], new[9]), (6, 1));
+        let new = ai_mcts_with(array, None, 1000, None, &rules, Some(0)).unwrap();
+        assert_eq!((new[0], new[9]), (6, 1));
+
+        let game = Game::from_position_with(".O./XX./O.. o 5", &rules).unwrap();
+        assert_eq!(game.rules(), rules);
+        assert_eq!(game.array(), vec![0, 1, 0, 2, 4, 0, 3, 0, 0]);
+    }
+
+    #[test]
+    /// with gravity the human drops in a column
+    /// and the AI answers through `ai_turn`
+    fn verify_gravity() {
+        let rules = Rules::sized(5, 4, 3, None, None, None).falling();
+        let array = make_drop_with(vec![0; 20], 2, &rules).unwrap();
+        assert_eq!(array[17], 1);
+        let array: Vec<u16> = array[..20].iter().map(|&n| n as u16).collect();
+        let (new, winner) = ai_turn(rules, array, None, Player::X, &Level::Search { depth: 4, nodes: None }, Some(0)).unwrap();
+        let tile = new.iter().position(|&n| n == 2).unwrap();
+        assert!(tile == 12 || (15..20).contains(&tile), "{}", tile);
+        assert_eq!(winner, 0);
+    }
+
+    #[test]
+    /// the AI can open the game when the human
+    /// goes second
+    fn verify_ai_turn_first() {
+        let rules = Rules::default().with_first(Player::X);
+        let (array, winner) = ai_turn(rules, vec![0; 9], None, Player::X, &Level::Heuristic(0.0), Some(0)).unwrap();
+        assert_eq!(array.iter().filter(|&&n| n == 1).count(), 1);
+        assert_eq!(winner, 0);
+
+        // the human plays O, so X can not move again
+        let result = ai_turn(rules, array, Some(0), Player::O, &Level::Heuristic(0.0), Some(0));
+        assert_eq!(result, Err(GameError::NotYourTurn(Player::X)));
+    }
+
+    #[test]
+    /// the search AI answers the human's move
+    /// with the winning one
+    fn verify_ai_turn_search() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 0,
+        ];
+        let level = Level::Search { depth: 4, nodes: Some(10_000) };
+        let (array, winner) = ai_turn(Rules::default(), array.to_vec(), Some(8), Player::X, &level, Some(0)).unwrap();
+        assert_eq!(array[0], 6);
+        assert_eq!(winner, 1);
+    }
+}
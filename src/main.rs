@@ -1,8 +1,8 @@
-use std::{collections::HashMap, io};
-use tic_tac_toe::{ai_turn, models::{Cell, GameState}, utils::print_state};
+use std::{collections::HashMap, error::Error, fs, io};
+use tic_tac_toe::{game::Game, models::{Board, Expiry, GameState, Player, Rules, Status}, notation::{column_name, parse_column, parse_tile, Record}, strategy::{self, Strategy}, utils::{print_state, today}};
 fn main() {
     let map = HashMap::from([
-        ("1", 6 as u16),
+        ("1", 6_u16),
         ("2", 7),
         ("3", 8),
         ("4", 3),
@@ -13,7 +13,10 @@ fn main() {
         ("9", 2),
     ]);
     loop {
-        println!("enter difficulty or c to exit: ");
+        println!(
+            "enter difficulty, p for perfect play, s<depth> to search, m<iterations> for monte carlo, a strategy ({}) like search:6, l <file> to load a game or c to exit: ",
+            strategy::names().join(", "),
+        );
         let mut choice = String::new();
         io::stdin()
         .read_line(&mut choice)
@@ -22,35 +25,108 @@ fn main() {
         if choice.trim() == "c" {
             return;
         }
-        let difficulty = choice.trim().parse::<f32>().unwrap_or(1.0);
+        let ai_strategy = match choose_strategy(choice.trim()) {
+            Some(ai_strategy) => ai_strategy,
+            None => {
+                println!("Unknown strategy {}", choice.trim());
+                continue;
+            },
+        };
 
-        let mut array = GameState::new().array;
+        let mut game = match choice.trim().strip_prefix("l ") {
+            Some(path) => match load(path) {
+                Ok(game) => game,
+                Err(error) => {
+                    println!("Could not load {}, {}", path, error);
+                    continue;
+                },
+            },
+            None => {
+                println!("enter the rules as width height win-length window, p after the window to keep it per player, n for no expiry, classic for normal tic-tac-toe, cube and a size for a 3D board, g at the end for falling marks or nothing for the infinite game: ");
+                let mut choice = String::new();
+                io::stdin()
+                .read_line(&mut choice)
+                .expect("Failed to read line");
+
+                match parse_rules(choice.trim()) {
+                    Some(rules) => Game::from_state(GameState::empty(rules)),
+                    None => {
+                        println!("Invalid rules {}", choice.trim());
+                        continue;
+                    },
+                }
+            },
+        };
+        let rules = game.state().rules;
+
+        println!("play first? (y/n): ");
+        let mut choice = String::new();
+        io::stdin()
+        .read_line(&mut choice)
+        .expect("Failed to read line");
+
+        let ai = if choice.trim() == "n" {
+            rules.first
+        } else {
+            rules.first.other()
+        };
+        println!("You play {:?}", ai.other());
+
+        if game.state().to_move() == ai && !game.state().is_over() {
+            game.ai_turn(ai, ai_strategy.as_ref()).expect("the AI can always move in a game that is not over");
+        }
+        print_state(game.state());
 
         loop {
-            println!("Next turn: ");
+            if rules.gravity {
+                println!("Next column to drop in like {}, or s <file> to save: ", column_name(&rules.board, 0));
+            } else if rules.board.is_cubic() {
+                println!("Next turn as column, row and layer like b2:1, or s <file> to save: ");
+            } else {
+                println!("Next turn, or s <file> to save: ");
+            }
             let mut choice = String::new();
             io::stdin()
             .read_line(&mut choice)
             .expect("Failed to read line");
-            
-            match map.get(&choice.trim()) {
-                Some(&tile) => {
-                    let (state, result) = match ai_turn(array, tile, difficulty) {
-                        Some(tuple) => tuple,
-                        None => {
-                            println!("Illegal move, try again: ");
-                            continue;
-                        },
-                    };
-                    print_state(&state);
-
-                    if result != 0 {
-                        let cell = Cell::cell(result);
-                        println!("{:?} Won", cell);
-                        break;
+
+            if let Some(path) = choice.trim().strip_prefix("s ") {
+                match save(&game, ai, path) {
+                    Ok(()) => println!("Saved to {}", path),
+                    Err(error) => println!("Could not save {}, {}", path, error),
+                }
+                continue;
+            }
+
+            // with gravity marks are dropped in a
+            // column instead of played on a tile
+            let played = if rules.gravity {
+                parse_column(&rules.board, choice.trim()).map(|column| game.drop_mark(column))
+            } else {
+                let tile = match map.get(&choice.trim()) {
+                    Some(&tile) if rules.board == Rules::default().board => Some(tile),
+                    _ => parse_tile(&rules.board, choice.trim()),
+                };
+                tile.map(|tile| game.turn(tile))
+            };
+            match played {
+                Some(played) => {
+                    if let Err(error) = played {
+                        println!("Illegal move, {}. Try again: ", error);
+                        continue;
                     }
+                    if !game.state().is_over() {
+                        game.ai_turn(ai, ai_strategy.as_ref()).expect("the AI can always move in a game that is not over");
+                    }
+                    print_state(game.state());
 
-                    array = state;
+                    match game.state().status() {
+                        Status::InProgress => continue,
+                        Status::Won(winner) => println!("{:?} Won", winner),
+                        Status::Drawn => println!("Draw"),
+                    }
+                    println!("{}", record(&game, ai));
+                    break;
                 },
                 None => break,
             }
@@ -58,7 +134,108 @@ fn main() {
     }
 }
 
+/// the strategy picked at the prompt, the
+/// shortcuts stand for the built in strategies
+/// and anything else is looked up by name
+fn choose_strategy(choice: &str) -> Option<Box<dyn Strategy>> {
+    let spec = match choice {
+        "p" => "perfect".to_string(),
+        choice if choice.starts_with("l ") => "heuristic".to_string(),
+        choice if choice.parse::<f32>().is_ok() => format!("heuristic:{}", choice),
+        choice if choice.starts_with('s') && choice[1..].parse::<u8>().is_ok() => format!("search:{}", &choice[1..]),
+        choice if choice.starts_with('m') && choice[1..].parse::<u32>().is_ok() => format!("mcts:{}", &choice[1..]),
+        "s" => "search".to_string(),
+        "m" => "mcts".to_string(),
+        "" => "heuristic".to_string(),
+        choice => choice.to_string(),
+    };
+    strategy::strategy(&spec)
+}
+
+/// the rules typed at the prompt like `4 4 3 8`,
+/// `4 4 3 3 p` or `4 4 3 n`, an empty line is the
+/// infinite game. Cubes are typed as `cube 3`,
+/// `cube 4 12 p` or `cube 3 n`. A `g` at the end
+/// makes the marks fall, like `7 6 4 12 g`.
+/// None if they can not be played, a `p`
+/// without a window or any other word after
+/// the board included
+fn parse_rules(choice: &str) -> Option<Rules> {
+    if let Some(rules) = choice.strip_suffix('g') {
+        return parse_rules(rules.trim()).map(Rules::with_gravity);
+    }
+    match choice {
+        "" => return Some(Rules::infinite()),
+        "classic" => return Some(Rules::classic()),
+        _ => {},
+    }
+    let words: Vec<&str> = choice.split_whitespace().collect();
+    let number = |word: &str| word.parse::<u16>().ok().filter(|&n| n > 0);
+    let (board, expiry) = match words[..] {
+        ["cube", size, ref expiry @ ..] => (Board::cube(number(size)?, number(size)?), expiry),
+        [width, height, win_length, ref expiry @ ..] => (Board::new(number(width)?, number(height)?, number(win_length)?), expiry),
+        _ => return None,
+    };
+    if board.win_length > board.width.max(board.height) {
+        return None;
+    }
+    let expiry = match *expiry {
+        [] => Expiry::default(),
+        ["n"] => Expiry::Never,
+        [window] => Expiry::Global(number(window)?),
+        [window, "p"] => Expiry::PerPlayer(number(window)?),
+        _ => return None,
+    };
+    Some(Rules::new(board, expiry))
+}
+
+/// reads a game record from a file
+fn load(path: &str) -> Result<Game, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    Ok(Record::parse(&text)?.to_game()?)
+}
+
+/// writes the game record to a file
+fn save(game: &Game, ai: Player, path: &str) -> io::Result<()> {
+    fs::write(path, record(game, ai).to_string())
+}
+
+/// the record of the game, naming the sides
+/// and dated today
+fn record(game: &Game, ai: Player) -> Record {
+    let name = |player: Player| Some(if player == ai { "AI" } else { "Player" }.to_string());
+    Record {
+        x: name(Player::X),
+        o: name(Player::O),
+        date: Some(today()),
+        ..Record::from_game(game)
+    }
+}
 
 /* io::stdin()
         .read_line(&mut guess)
-        .expect("Failed to read line"); */
\ No newline at end of file
+        .expect("Failed to read line"); */
+
+#[cfg(test)]
+mod test {
+    use tic_tac_toe::models::{Board, Expiry, Rules};
+
+    use super::parse_rules;
+
+    #[test]
+    /// every form the prompt offers, and the
+    /// words it does not know are refused
+    fn main_parse_rules() {
+        assert_eq!(parse_rules(""), Some(Rules::infinite()));
+        assert_eq!(parse_rules("4 4 3 8"), Some(Rules::new(Board::new(4, 4, 3), Expiry::Global(8))));
+        assert_eq!(parse_rules("4 4 3 3 p"), Some(Rules::new(Board::new(4, 4, 3), Expiry::PerPlayer(3))));
+        assert_eq!(parse_rules("4 4 3 n"), Some(Rules::new(Board::new(4, 4, 3), Expiry::Never)));
+        assert_eq!(parse_rules("cube 3 n"), Some(Rules::cube(3, None, None, None).without_expiry()));
+        assert_eq!(parse_rules("7 6 4 12 g"), Some(Rules::new(Board::new(7, 6, 4), Expiry::Global(12)).with_gravity()));
+        assert_eq!(parse_rules("4 4 3"), Some(Rules::sized(4, 4, 3, None, None, None)));
+
+        for choice in ["4 4 3 p", "cube 3 p", "4 4 3 8 q", "4 4 3 n 8", "4 4 3 8 p x", "4 4 5", "4 4"] {
+            assert_eq!(parse_rules(choice), None, "{}", choice);
+        }
+    }
+}
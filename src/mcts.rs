@@ -0,0 +1,262 @@
+use rand::Rng;
+
+use crate::ai;
+use crate::models::{Cell, GameState};
+use crate::position::{Moves, Position};
+use crate::search;
+
+/// How moves are picked in the random games
+/// played out from the tree
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Playout {
+    /// any legal move
+    Random,
+    /// a move that wins at once if there is one,
+    /// otherwise any legal move
+    WinFirst,
+    /// the best move of the one move lookahead
+    /// heuristic, slower but stronger
+    Heuristic,
+}
+
+/// The settings of the Monte Carlo tree search
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MctsConfig {
+    /// random games played before moving
+    pub iterations: u32,
+    /// moves after which a random game that
+    /// nobody has won counts as a draw
+    pub max_moves: u16,
+    pub playout: Playout,
+    /// how much UCT favours moves that have been
+    /// tried less over ones that scored well
+    pub exploration: f32,
+}
+impl Default for MctsConfig {
+    fn default() -> Self {
+        Self {
+            iterations: 1000,
+            max_moves: 100,
+            playout: Playout::Random,
+            exploration: std::f32::consts::SQRT_2,
+        }
+    }
+}
+
+/// One node of the tree, scored for the player
+/// who made the move leading to it
+#[derive(Clone, Debug)]
+struct Node {
+    position: Position,
+    parent: Option<usize>,
+    tile: u16,
+    children: Vec<usize>,
+    untried: u64,
+    visits: u32,
+    score: f32,
+}
+impl Node {
+    fn new(position: Position, parent: Option<usize>, tile: u16) -> Self {
+        Self {
+            position,
+            parent,
+            tile,
+            children: Vec::new(),
+            untried: position.legal_moves().0,
+            visits: 0,
+            score: 0.0,
+        }
+    }
+}
+
+/// Picks a move with Monte Carlo tree search and
+/// UCT, None if no move can be made. The move
+/// tried most often wins. Boards too large for
+/// a bitboard fall back to the heuristic
+pub fn best_move<R: Rng + ?Sized>(game_state: &GameState, config: MctsConfig, rng: &mut R) -> Option<u16> {
+    let root = match Position::from_state(game_state) {
+        Ok(position) => position,
+        Err(_) => return ai::turn(game_state, 0.0, rng),
+    };
+    let tree = grow(root, config, rng);
+    tree[0]
+    .children
+    .iter()
+    .max_by_key(|&&child| tree[child].visits)
+    .map(|&child| tree[child].tile)
+}
+
+/// private function. Runs the iterations of the
+/// search and returns the tree, the root first
+fn grow<R: Rng + ?Sized>(root: Position, config: MctsConfig, rng: &mut R) -> Vec<Node> {
+    let mut tree = vec![Node::new(root, None, 0)];
+    for _ in 0..config.iterations {
+        // selection, down the fully expanded nodes
+        let mut id = 0;
+        while tree[id].untried == 0 && !tree[id].children.is_empty() {
+            let parent_visits = tree[id].visits as f32;
+            id = *tree[id]
+            .children
+            .iter()
+            .max_by(|&&a, &&b| {
+                let uct = |node: &Node| {
+                    node.score / node.visits as f32
+                        + config.exploration * (parent_visits.ln() / node.visits as f32).sqrt()
+                };
+                uct(&tree[a]).total_cmp(&uct(&tree[b]))
+            })
+            .unwrap();
+        }
+
+        // expansion, one untried move
+        if tree[id].untried != 0 {
+            let tile = random_tile(tree[id].untried, rng);
+            tree[id].untried &= !(1 << tile);
+            let child = Node::new(tree[id].position.play(tile), Some(id), tile);
+            tree.push(child);
+            let child = tree.len() - 1;
+            tree[id].children.push(child);
+            id = child;
+        }
+
+        // simulation and backpropagation, a draw
+        // is half a win
+        let winner = playout(tree[id].position, config, rng);
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let mover = tree[id].position.to_move().other().cell();
+            tree[id].visits += 1;
+            tree[id].score += match winner {
+                Cell::N => 0.5,
+                winner if winner == mover => 1.0,
+                _ => 0.0,
+            };
+            node = tree[id].parent;
+        }
+    }
+    tree
+}
+
+/// private function. Plays a game out with the
+/// playout policy and returns the winner, None
+/// if nobody won within the move cap
+fn playout<R: Rng + ?Sized>(mut position: Position, config: MctsConfig, rng: &mut R) -> Cell {
+    for _ in 0..config.max_moves {
+        let winner = position.winner();
+        if winner != Cell::N {
+            return winner;
+        }
+        let moves = position.legal_moves();
+        if moves.0 == 0 {
+            return Cell::N;
+        }
+        let tile = match config.playout {
+            Playout::Random => random_tile(moves.0, rng),
+            Playout::WinFirst => {
+                let mover = position.to_move().cell();
+                let mut wins = moves;
+                wins
+                .find(|&tile| position.play(tile).winner() == mover)
+                .unwrap_or_else(|| random_tile(moves.0, rng))
+            },
+            Playout::Heuristic => moves
+            .map(|tile| (tile, -search::evaluate(&position.play(tile))))
+            .fold(None, |best: Option<(u16, f32)>, (tile, score)| match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((tile, score)),
+            })
+            .map(|(tile, _)| tile)
+            .unwrap(),
+        };
+        position = position.play(tile);
+    }
+    position.winner()
+}
+
+/// private function. A random tile of a non
+/// empty bitboard
+fn random_tile<R: Rng + ?Sized>(tiles: u64, rng: &mut R) -> u16 {
+    let n = rng.gen_range(0..tiles.count_ones() as usize);
+    Moves(tiles).nth(n).unwrap()
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::{best_move, grow, playout, MctsConfig, Playout};
+    use crate::models::{Board, Cell, Expiry, GameState, Rules};
+    use crate::position::Position;
+
+    #[test]
+    fn mcts_winning_move() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let game_state = GameState::from_array(array);
+        for playout in [Playout::Random, Playout::WinFirst, Playout::Heuristic] {
+            let config = MctsConfig { playout, ..MctsConfig::default() };
+            assert_eq!(best_move(&game_state, config, &mut StdRng::seed_from_u64(0)), Some(0), "{:?}", playout);
+        }
+    }
+
+    #[test]
+    fn mcts_blocks() {
+        let array = [
+            1, 2, 0,
+            0, 3, 0,
+            0, 0, 0,
+        ];
+        let game_state = GameState::from_array(array);
+        let config = MctsConfig { iterations: 2000, ..MctsConfig::default() };
+        assert_eq!(best_move(&game_state, config, &mut StdRng::seed_from_u64(0)), Some(8));
+    }
+
+    #[test]
+    /// every iteration adds one node and visits
+    /// the root and one of its moves, and the same
+    /// seed grows the same tree
+    fn mcts_iterations() {
+        let position = Position::empty(Rules::default()).unwrap();
+        let config = MctsConfig { iterations: 300, ..MctsConfig::default() };
+        let tree = grow(position, config, &mut StdRng::seed_from_u64(1));
+        assert_eq!(tree[0].visits, 300);
+        assert_eq!(tree.len(), 301);
+        let visits: u32 = tree[0].children.iter().map(|&child| tree[child].visits).sum();
+        assert_eq!(visits, 300);
+
+        let again = grow(position, config, &mut StdRng::seed_from_u64(1));
+        let moves = |tree: &Vec<super::Node>| tree.iter().map(|node| (node.tile, node.visits)).collect::<Vec<_>>();
+        assert_eq!(moves(&tree), moves(&again));
+    }
+
+    #[test]
+    /// a game cut short by the move cap is a draw
+    fn mcts_move_cap() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::Global(6));
+        let position = Position::empty(rules).unwrap();
+        let config = MctsConfig { max_moves: 500, ..MctsConfig::default() };
+        // four in a row can not be made with three marks
+        assert_eq!(playout(position, config, &mut StdRng::seed_from_u64(0)), Cell::N);
+    }
+
+    #[test]
+    fn mcts_large_board() {
+        let rules = Rules::new(Board::square(8, 5), Expiry::Global(12));
+        let game_state = GameState::empty(rules);
+        let config = MctsConfig { iterations: 200, max_moves: 40, ..MctsConfig::default() };
+        let tile = best_move(&game_state, config, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert!(tile < 64);
+    }
+}
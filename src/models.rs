@@ -1,9 +1,21 @@
-/// X = 1 = even
-/// O = -1 = odd
+use std::convert::TryFrom;
+use std::fmt;
+
+use wasm_bindgen::prelude::*;
+
+use crate::error::GameError;
+use crate::notation::{parse_tile, tile_name, NotationError};
+
+/// Move numbers are renumbered once the last
+/// move reaches this, so the number of the next
+/// move always fits. See `GameState::renumber`
+pub const RENUMBER_AT: u16 = u16::MAX - 1;
+
+/// X = 1, O = -1
 /// Describes the state of one cell, it can be X, O, None or Expiring
 /// Expiring is seperate because it is not counted towards a win
 /// but also inhibits making a turn at that cell
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Cell {
     X, O, E, N
 }
@@ -19,22 +31,624 @@ impl Cell {
         }
     }
 
-    pub fn cell(i: i32) -> Self {
+    /// inverse of `number`, anything that is
+    /// not a player maps to None
+    pub fn from_number(i: i32) -> Self {
         match i {
             1 => Self::X,
             -1 => Self::O,
             _ => Self::N,
         }
     } 
+
+    /// the old name of `from_number`, kept so
+    /// existing callers still build
+    #[deprecated(note = "use `Cell::from_number`")]
+    #[allow(clippy::self_named_constructors)]
+    pub fn cell(i: i32) -> Self {
+        Self::from_number(i)
+    }
+}
+
+/// One of the two sides of the game
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Player {
+    X, O
+}
+impl Player {
+    /// the cell this player's marks show as
+    pub fn cell(&self) -> Cell {
+        match self {
+            Player::X => Cell::X,
+            Player::O => Cell::O,
+        }
+    }
+
+    pub fn other(&self) -> Player {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+        }
+    }
+
+    /// numeric representation to be passed to
+    /// javascript, the same as `Cell::number`
+    pub fn number(&self) -> i32 {
+        self.cell().number()
+    }
+
+    /// inverse of `number`
+    pub fn from_number(i: i32) -> Option<Self> {
+        match i {
+            1 => Some(Player::X),
+            -1 => Some(Player::O),
+            _ => None,
+        }
+    }
+}
+impl Default for Player {
+    /// O has always moved first
+    fn default() -> Self {
+        Player::O
+    }
+}
+
+/// Dimensions of the grid and the number of marks
+/// in a row needed to win. The default is the
+/// classic 3x3 grid with 3 in a row. Boards with
+/// a `depth` above 1 are cubic: layers of
+/// `width` x `height` tiles stacked on top of
+/// each other, with lines running through the
+/// layers as well
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Board {
+    pub width: u16,
+    pub height: u16,
+    /// the number of layers, 1 for a flat board
+    pub depth: u16,
+    pub win_length: u16,
+}
+impl Board {
+    pub fn new(width: u16, height: u16, win_length: u16) -> Self {
+        Self::layered(width, height, 1, win_length)
+    }
+
+    /// a square board of the given side
+    pub fn square(size: u16, win_length: u16) -> Self {
+        Self::new(size, size, win_length)
+    }
+
+    /// a board of `depth` layers of the given
+    /// width and height
+    pub fn layered(width: u16, height: u16, depth: u16, win_length: u16) -> Self {
+        Self { width, height, depth, win_length }
+    }
+
+    /// a cube of the given side, like the 3x3x3
+    /// and 4x4x4 games
+    pub fn cube(size: u16, win_length: u16) -> Self {
+        Self::layered(size, size, size, win_length)
+    }
+
+    /// true for boards of more than one layer
+    pub fn is_cubic(&self) -> bool {
+        self.depth > 1
+    }
+
+    /// number of cells on the board, which is
+    /// also the length of the array representation
+    pub fn len(&self) -> usize {
+        self.width as usize * self.height as usize * self.depth as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the column, row and layer of an array
+    /// index. Tiles are numbered along the rows
+    /// of the top layer first, then the layers
+    /// below it
+    pub fn coordinates(&self, tile: u16) -> (u16, u16, u16) {
+        let (column, row) = (tile % self.width, tile / self.width);
+        (column, row % self.height, row / self.height)
+    }
+
+    /// inverse of `coordinates`, None if the
+    /// tile is not on the board
+    pub fn tile(&self, column: u16, row: u16, layer: u16) -> Option<u16> {
+        if column >= self.width || row >= self.height || layer >= self.depth {
+            return None;
+        }
+        Some((layer * self.height + row) * self.width + column)
+    }
+
+    /// the number of columns marks can be dropped
+    /// in with gravity, `width` on every layer
+    pub fn columns(&self) -> u16 {
+        self.width * self.depth
+    }
+
+    /// the column of a tile, counted along the top
+    /// row of every layer in turn
+    pub fn column(&self, tile: u16) -> u16 {
+        let (column, _, layer) = self.coordinates(tile);
+        layer * self.width + column
+    }
+
+    /// the tiles of a column from the bottom row
+    /// up, empty if there is no such column
+    pub fn column_tiles(&self, column: u16) -> Vec<u16> {
+        (0..self.height)
+        .rev()
+        .filter_map(|row| self.tile(column % self.width, row, column / self.width))
+        .collect()
+    }
+
+    /// Generates every row, column and diagonal
+    /// segment of `win_length` cells that fits on
+    /// the board, as array indices. Cubic boards
+    /// also have the lines through the layers: the
+    /// 13 directions of a cube give 49 lines on the
+    /// 3x3x3 board and 76 on the 4x4x4 one
+    pub fn lines(&self) -> Vec<Vec<u16>> {
+        let k = self.win_length as i32;
+        let mut lines = Vec::new();
+        if k == 0 {
+            return lines;
+        }
+
+        // the directions within a layer come first,
+        // then the ones going down through the layers
+        let mut directions = vec![(0, 0, 1), (0, 1, 0), (0, 1, 1), (0, 1, -1)];
+        if self.is_cubic() {
+            for dr in -1..=1 {
+                for dc in -1..=1 {
+                    directions.push((1, dr, dc));
+                }
+            }
+        }
+        let within = |n: i32, size: u16| n >= 0 && n < i32::from(size);
+        for (dl, dr, dc) in directions {
+            for layer in 0..i32::from(self.depth) {
+                for row in 0..i32::from(self.height) {
+                    for col in 0..i32::from(self.width) {
+                        let end = (layer + dl * (k - 1), row + dr * (k - 1), col + dc * (k - 1));
+                        if !within(end.0, self.depth) || !within(end.1, self.height) || !within(end.2, self.width) {
+                            continue;
+                        }
+                        let line = (0..k)
+                        .map(|n| {
+                            let (l, r, c) = (layer + dl * n, row + dr * n, col + dc * n);
+                            ((l * i32::from(self.height) + r) * i32::from(self.width) + c) as u16
+                        })
+                        .collect();
+                        lines.push(line);
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
+impl Default for Board {
+    fn default() -> Self {
+        Self::square(3, 3)
+    }
+}
+
+/// How many marks stay on the board before the
+/// oldest one starts expiring
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Expiry {
+    /// at most this many marks on the whole board,
+    /// the infinite game keeps 6
+    Global(u16),
+    /// each player keeps at most this many of
+    /// their own most recent marks
+    PerPlayer(u16),
+    /// marks stay until the game ends, as in
+    /// classic tic-tac-toe
+    Never,
+}
+impl Expiry {
+    /// the number of marks that are kept,
+    /// `u16::MAX` if marks never expire
+    pub fn window(&self) -> u16 {
+        match self {
+            Expiry::Global(window) | Expiry::PerPlayer(window) => *window,
+            Expiry::Never => u16::MAX,
+        }
+    }
+}
+impl Default for Expiry {
+    fn default() -> Self {
+        Expiry::Global(6)
+    }
+}
+
+/// The variant being played: the board, the
+/// expiry window and who moves first. Defaults
+/// to the infinite 3x3 game where six marks stay
+/// on the board and O starts.
+/// Odd move numbers belong to the first player
+/// and even ones to the second.
+/// Every rule of the game is decided here and
+/// `GameState` only keeps the marks: which tiles
+/// can be played (`legal_moves`), which mark
+/// expires (`expiring_move`), which lines win
+/// (`winning_lines`) and when the game is over
+/// (`is_over`). A new variant is a new setting
+/// of the rules, not a change to `GameState`
+#[wasm_bindgen]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Rules {
+    #[wasm_bindgen(skip)]
+    pub board: Board,
+    #[wasm_bindgen(skip)]
+    pub expiry: Expiry,
+    #[wasm_bindgen(skip)]
+    pub first: Player,
+    /// marks are dropped in a column and fall to
+    /// its lowest free tile, like Connect Four.
+    /// When an expiring mark is removed the marks
+    /// above it fall one tile down
+    #[wasm_bindgen(skip)]
+    pub gravity: bool,
+}
+impl Rules {
+    pub fn new(board: Board, expiry: Expiry) -> Self {
+        Self { board, expiry, first: Player::default(), gravity: false }
+    }
+
+    /// the same rules with another first player
+    pub fn with_first(self, first: Player) -> Self {
+        Self { first, ..self }
+    }
+
+    /// the same rules where marks fall to the
+    /// bottom of their column
+    pub fn with_gravity(self) -> Self {
+        Self { gravity: true, ..self }
+    }
+
+    /// the player who makes move number `n`
+    pub fn player(&self, n: u16) -> Player {
+        if n % 2 == 1 {
+            self.first
+        } else {
+            self.first.other()
+        }
+    }
+
+    /// the cell shown for a move number, 0 is
+    /// an empty cell
+    pub fn owner(&self, n: u16) -> Cell {
+        if n == 0 {
+            Cell::N
+        } else {
+            self.player(n).cell()
+        }
+    }
+
+    /// the move numbers that are still on the board
+    /// after move `last` has been made, in order
+    pub fn live_moves(&self, last: u16) -> Vec<u16> {
+        match self.expiry {
+            Expiry::Global(window) => {
+                (last.saturating_sub(window) + 1..=last).collect()
+            },
+            Expiry::Never => (1..=last).collect(),
+            Expiry::PerPlayer(window) => {
+                let mut moves: Vec<u16> = (1..=last)
+                .rev()
+                .filter(|&n| n % 2 == 1)
+                .take(window as usize)
+                .chain((1..=last).rev().filter(|&n| n % 2 == 0).take(window as usize))
+                .collect();
+                moves.sort_unstable();
+                moves
+            },
+        }
+    }
+
+    /// the mark that is removed by the move after
+    /// `last`, out of the move numbers on the board.
+    /// Expiry starts once enough moves have been
+    /// made, and only the oldest counted mark is
+    /// ever expiring
+    pub fn expiring_move(&self, last: u16, numbers: &[u16]) -> Option<u16> {
+        let next = last + 1;
+        let moves = match self.expiry {
+            Expiry::Global(_) | Expiry::Never => last,
+            Expiry::PerPlayer(_) => (last + next % 2) / 2,
+        };
+        self.expiring_after(moves, next, numbers)
+    }
+
+    /// the oldest counted mark out of the move
+    /// numbers once `moves` counted marks have
+    /// been played, for the player who makes move
+    /// `next`. Games that count marks outside the
+    /// move numbers, like `Ultimate`, call it
+    /// directly
+    pub(crate) fn expiring_after(&self, moves: u16, next: u16, numbers: &[u16]) -> Option<u16> {
+        if moves < self.expiry.window() {
+            return None;
+        }
+        numbers
+        .iter()
+        .copied()
+        .filter(|&n| n != 0 && self.counts(n, next))
+        .min()
+    }
+
+    /// whether the mark with move number `n`
+    /// counts for the window of the player who
+    /// makes move `next`, with a window per
+    /// player only their own marks do
+    pub(crate) fn counts(&self, n: u16, next: u16) -> bool {
+        match self.expiry {
+            Expiry::PerPlayer(_) => n % 2 == next % 2,
+            Expiry::Global(_) | Expiry::Never => true,
+        }
+    }
+
+    /// the tile a mark dropped in the column lands
+    /// on: its lowest empty tile. None if the
+    /// column is full or not on the board
+    pub fn drop_tile(&self, game_state: &GameState, column: u16) -> Option<u16> {
+        self.board
+        .column_tiles(column)
+        .into_iter()
+        .find(|&tile| game_state.get(tile) == Cell::N)
+    }
+
+    /// checks that the player to move may play
+    /// on the tile, the reason they may not is
+    /// returned as an error. With gravity only
+    /// the tile a mark dropped in its column
+    /// lands on can be played
+    pub fn check_move(&self, game_state: &GameState, tile: u16) -> Result<(), GameError> {
+        if tile as usize >= game_state.array.len() {
+            return Err(GameError::OutOfBounds(tile));
+        }
+        match game_state.get(tile) {
+            Cell::N => {},
+            Cell::E => return Err(GameError::ExpiringCell(tile)),
+            _ => return Err(GameError::OccupiedCell(tile)),
+        }
+        let winner = game_state.winner();
+        if winner != Cell::N {
+            return Err(GameError::GameWon(winner));
+        }
+        if self.gravity && self.drop_tile(game_state, self.board.column(tile)) != Some(tile) {
+            return Err(GameError::FloatingTile(tile));
+        }
+        Ok(())
+    }
+
+    /// the tiles the player to move can play on:
+    /// every empty tile, or with gravity the tile
+    /// every column that is not full drops to.
+    /// None once the game has been won
+    pub fn legal_moves(&self, game_state: &GameState) -> Vec<u16> {
+        if game_state.winner() != Cell::N {
+            return Vec::new();
+        }
+        if self.gravity {
+            let mut tiles: Vec<u16> = (0..self.board.columns())
+            .filter_map(|column| self.drop_tile(game_state, column))
+            .collect();
+            tiles.sort_unstable();
+            return tiles;
+        }
+        (0..game_state.array.len() as u16)
+        .filter(|&i| game_state.get(i) == Cell::N)
+        .collect()
+    }
+
+    /// every line of the board filled by one
+    /// player, expiring marks do not count
+    pub fn winning_lines(&self, game_state: &GameState) -> Vec<Vec<u16>> {
+        self.board
+        .lines()
+        .into_iter()
+        .filter(|possibility| {
+            let cell = game_state.get(possibility[0]);
+            cell != Cell::N && cell != Cell::E
+                && possibility.iter().all(|&i| game_state.get(i) == cell)
+        })
+        .collect()
+    }
+
+    /// true once no more moves can be made,
+    /// because someone has won or no tile is free
+    pub fn is_over(&self, game_state: &GameState) -> bool {
+        self.legal_moves(game_state).is_empty()
+    }
+
+    /// whether the game goes on, has been won or
+    /// is drawn because no move can be made. With
+    /// expiring marks there is always a free tile
+    /// unless the window is as big as the board
+    pub fn status(&self, game_state: &GameState) -> Status {
+        match game_state.winner() {
+            Cell::X => Status::Won(Player::X),
+            Cell::O => Status::Won(Player::O),
+            _ if self.is_over(game_state) => Status::Drawn,
+            _ => Status::InProgress,
+        }
+    }
+
+    /// the same rules with marks that never
+    /// expire, see `Rules::classic`
+    pub fn without_expiry(self) -> Self {
+        Self { expiry: Expiry::Never, ..self }
+    }
+}
+
+#[wasm_bindgen]
+impl Rules {
+    #[wasm_bindgen(constructor)]
+    /// WASM Function
+    /// the rules for a board of any size and win
+    /// length. The window defaults to 6 marks on
+    /// the board, or is counted per player if
+    /// asked. `first` is 1 for X and -1 for O,
+    /// the default
+    pub fn sized(width: u16, height: u16, win_length: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Self {
+        let board = Board::new(width, height, win_length);
+        let expiry = match (window, per_player.unwrap_or(false)) {
+            (Some(window), true) => Expiry::PerPlayer(window),
+            (Some(window), false) => Expiry::Global(window),
+            (None, _) => Expiry::default(),
+        };
+        let first = first.and_then(Player::from_number).unwrap_or_default();
+        Rules::new(board, expiry).with_first(first)
+    }
+
+    /// WASM Function
+    /// the rules for a cubic board of the given
+    /// side where lines of `size` marks win, like
+    /// the 3x3x3 and 4x4x4 games. The window and
+    /// first player are the same as for `sized`,
+    /// `never_expiring` gives the classic game
+    pub fn cube(size: u16, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Self {
+        let rules = Rules::sized(size, size, size, window, per_player, first);
+        Rules { board: Board::cube(size, size), ..rules }
+    }
+
+    /// WASM Function
+    /// the infinite 3x3 game where six marks
+    /// stay on the board, the default
+    pub fn infinite() -> Self {
+        Rules::default()
+    }
+
+    /// WASM Function
+    /// classic tic-tac-toe on a 3x3 board where
+    /// marks never expire and a full board is a
+    /// draw, O starts
+    pub fn classic() -> Self {
+        Rules::default().without_expiry()
+    }
+
+    /// WASM Function
+    /// the same rules with marks that never
+    /// expire
+    pub fn never_expiring(&self) -> Rules {
+        self.without_expiry()
+    }
+
+    /// WASM Function
+    /// the same rules where marks are dropped in
+    /// a column and fall to its lowest free tile
+    pub fn falling(&self) -> Rules {
+        self.with_gravity()
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    pub fn width(&self) -> u16 {
+        self.board.width
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    pub fn height(&self) -> u16 {
+        self.board.height
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    /// the number of layers, 1 for a flat board
+    pub fn depth(&self) -> u16 {
+        self.board.depth
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    pub fn win_length(&self) -> u16 {
+        self.board.win_length
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    /// the number of marks that are kept
+    pub fn window(&self) -> u16 {
+        self.expiry.window()
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    /// false if marks never expire
+    pub fn expires(&self) -> bool {
+        self.expiry != Expiry::Never
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    /// true if every player keeps `window` marks
+    pub fn per_player(&self) -> bool {
+        matches!(self.expiry, Expiry::PerPlayer(_))
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    /// 1 if X moves first, -1 for O
+    pub fn first(&self) -> i32 {
+        self.first.number()
+    }
+
+    #[wasm_bindgen(getter)]
+    /// WASM Function
+    /// true if marks fall to the bottom of
+    /// their column
+    pub fn gravity(&self) -> bool {
+        self.gravity
+    }
+}
+
+/// How a game stands
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Status {
+    InProgress,
+    Won(Player),
+    /// nobody has won and no move can be made
+    Drawn,
+}
+impl Status {
+    /// numeric representation to be passed to
+    /// javascript: 1 = X won, -1 = O won, 0 in
+    /// progress and 2 drawn
+    pub fn number(&self) -> i32 {
+        match self {
+            Status::InProgress => 0,
+            Status::Won(player) => player.number(),
+            Status::Drawn => 2,
+        }
+    }
+}
+
+/// A finished game: who won and every line
+/// they completed, as array indices
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Win {
+    pub winner: Cell,
+    pub lines: Vec<Vec<u16>>,
 }
 
 /// Represents one instance of a game and holds
 /// the array representation, grid of cells, 
-/// and the latest and oldest turn on the grid
-#[derive(PartialEq, Debug, Clone, Copy)]
+/// and the latest and oldest turn on the grid.
+/// The grid holds the rows of every layer, from
+/// the top layer down
+#[derive(PartialEq, Debug, Clone)]
 pub struct GameState {
-    pub array: [u16;9],
-    pub state: [[Cell;3];3],
+    pub rules: Rules,
+    pub array: Vec<u16>,
+    pub state: Vec<Vec<Cell>>,
     pub expiring: u16,
     pub last: u16,
 }
@@ -44,60 +658,177 @@ impl GameState {
         GameState::from_array([0;9])
     }
 
+    /// an empty game with the given rules
+    pub fn empty(rules: Rules) -> Self {
+        GameState::with_rules(rules, vec![0; rules.board.len()])
+    }
+
+    /// parses an array of numbers received from 
+    /// javascript for the default 3x3 game
+    pub fn from_array(array: [u16; 9]) -> Self {
+        GameState::with_rules(Rules::default(), array.to_vec())
+    }
+
+    /// parses an array for the given board with
+    /// the default expiry window
+    pub fn with_board(board: Board, array: Vec<u16>) -> Self {
+        GameState::with_rules(Rules { board, ..Rules::default() }, array)
+    }
+
     /// parses an array of numbers received from 
     /// javascript and creates an instance.
     /// Correctly identifies latest and expiring
-    /// turns
-    pub fn from_array(array: [u16; 9]) -> Self {
+    /// turns. The array is padded with zeroes or
+    /// truncated to fit the board, and renumbered
+    /// if its numbers are too high to play on
+    pub fn with_rules(rules: Rules, mut array: Vec<u16>) -> Self {
+        let board = rules.board;
+        array.resize(board.len(), 0);
+
         let mut state = Self {
+            rules,
             array,
             expiring: 0,
-            state: [[Cell::N;3];3],
+            state: vec![vec![Cell::N; board.width as usize]; board.height as usize * board.depth as usize],
             last: 0,
         };
-        let mut smallest = (300, 0, 0);
-        let mut largest = 0;
-
-        for i in 0..3 {
-            for j in 0..3 {
-                let index = i*3 + j;
-                let num = array[index];
-
-                state.state[i][j] = if num == 0 {
-                    Cell::N
-                } else if num % 2 == 0 {
-                    Cell::X
-                } else if num % 2 == 1 {
-                    Cell::O
-                } else {
-                    Cell::N
-                };
 
-                if num < smallest.0 && num != 0 {
-                    smallest.0 = num;
-                    smallest.1 = i;
-                    smallest.2 = j;
-                }
-                if num > largest {
-                    largest = num;
-                }
-            }
+        for i in 0..board.len() as u16 {
+            let num = state.array[i as usize];
+            state.set(i, rules.owner(num));
         }
-        if largest >= 6 {
-            state.state[smallest.1][smallest.2] = Cell::E;
-            state.expiring = smallest.0;
+        state.last = state.array.iter().copied().max().unwrap_or(0);
+        if state.last >= RENUMBER_AT {
+            state.renumber();
         }
-        state.last = largest;
+        state.mark_expiring();
 
         state
     }
 
+    /// renumbers the marks from 1 or 2 upwards
+    /// keeping their order and who made them, so
+    /// the oldest mark is move 1 or 2. Only the
+    /// order of the marks matters to the game,
+    /// which plays on exactly as before. `turn`
+    /// does this on its own once the last move
+    /// reaches `RENUMBER_AT`, so games can go on
+    /// forever and arrays stay in the same format
+    pub fn renumber(&mut self) {
+        let mut numbers: Vec<u16> = self.array.iter().copied().filter(|&n| n != 0).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+
+        let mut renumbered = Vec::with_capacity(numbers.len());
+        let mut previous = 0;
+        for &n in &numbers {
+            // the next number with the same parity
+            previous += if (previous + 1) % 2 == n % 2 { 1 } else { 2 };
+            renumbered.push(previous);
+        }
+        let new = |n: u16| match numbers.binary_search(&n) {
+            Ok(i) => renumbered[i],
+            Err(_) => 0,
+        };
+
+        for n in self.array.iter_mut() {
+            *n = new(*n);
+        }
+        self.last = new(self.last);
+        self.expiring = new(self.expiring);
+    }
+
+    /// parses an array like `with_rules` but
+    /// rejects arrays of the wrong length and
+    /// positions that can not happen in a game,
+    /// see `validate`
+    pub fn try_with_rules(rules: Rules, array: &[u16]) -> Result<Self, GameError> {
+        if array.len() != rules.board.len() {
+            return Err(GameError::WrongLength { expected: rules.board.len(), found: array.len() });
+        }
+        let state = GameState::with_rules(rules, array.to_vec());
+        state.validate()?;
+        Ok(state)
+    }
+
+    /// checks that the array is a position that
+    /// can be reached by playing the game: every
+    /// move number appears once, the marks on the
+    /// board are exactly the ones the expiry window
+    /// keeps, and only the last move can have
+    /// completed a line. With gravity no mark can
+    /// be above an empty tile, and lines made by
+    /// falling marks are not checked as they can
+    /// be anywhere. The first problem found is
+    /// returned
+    pub fn validate(&self) -> Result<(), GameError> {
+        let mut numbers: Vec<u16> = self.array.iter().copied().filter(|&n| n != 0).collect();
+        numbers.sort_unstable();
+        if let Some(pair) = numbers.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(GameError::DuplicateMove(pair[0]));
+        }
+
+        let window = self.rules.expiry.window();
+        let count = match self.rules.expiry {
+            Expiry::Global(_) | Expiry::Never => numbers.len(),
+            Expiry::PerPlayer(_) => {
+                let odd = numbers.iter().filter(|&&n| n % 2 == 1).count();
+                odd.max(numbers.len() - odd)
+            },
+        };
+        if count > window as usize {
+            return Err(GameError::TooManyMarks { count, window });
+        }
+
+        let live = self.rules.live_moves(self.last);
+        if let Some(&n) = numbers.iter().find(|n| !live.contains(n)) {
+            return Err(GameError::StaleMove(n));
+        }
+        if let Some(&n) = live.iter().find(|n| !numbers.contains(n)) {
+            return Err(GameError::MissingMove(n));
+        }
+
+        if self.rules.gravity {
+            let board = self.board();
+            let floating = (0..board.len() as u16).find(|&tile| {
+                let (column, row, layer) = board.coordinates(tile);
+                let below = board.tile(column, row + 1, layer);
+                self.array[tile as usize] != 0 && below.map_or(false, |below| self.array[below as usize] == 0)
+            });
+            if let Some(tile) = floating {
+                return Err(GameError::FloatingTile(tile));
+            }
+            if self.rules.expiry != Expiry::Never {
+                return Ok(());
+            }
+        }
+
+        let last_tile = self.array.iter().position(|&n| n == self.last && n != 0);
+        for line in self.winning_lines() {
+            if !last_tile.map_or(false, |tile| line.contains(&(tile as u16))) {
+                return Err(GameError::UnreachableWin);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// the player who makes the next move
+    pub fn to_move(&self) -> Player {
+        self.rules.player(self.last + 1)
+    }
+
+    /// the board of the rules being played
+    pub fn board(&self) -> &Board {
+        &self.rules.board
+    }
+
     /// accesses grid cell based on its
-    /// corresponding index in the array
+    /// corresponding index in the array.
+    /// Panics if the index is not on the board
     pub fn get(&self, i: u16) -> Cell {
-        let j = (i % 3) as usize;
-        let i = (i / 3) as usize;
-        self.state[i][j]
+        let (row, column) = self.grid_index(i);
+        self.state[row][column]
     }
 
     /// private function. Sets the cell at given
@@ -105,75 +836,329 @@ impl GameState {
     /// NOT perform any validations, assuming it
     /// to be the calling function's responsibility
     fn set(&mut self, i: u16, cell: Cell) {
-        
-        let j = (i % 3) as usize;
-        let i = (i / 3) as usize;
-        self.state[i][j] = cell;
+        let (row, column) = self.grid_index(i);
+        self.state[row][column] = cell;
+    }
+
+    /// private function. The row of the grid and
+    /// the column in it of an array index, see
+    /// `Board::coordinates`
+    fn grid_index(&self, i: u16) -> (usize, usize) {
+        let board = self.board();
+        let (column, row, layer) = board.coordinates(i);
+        (layer as usize * board.height as usize + row as usize, column as usize)
+    }
+
+    /// marks the mark on a tile as the one the
+    /// next move removes, for games that decide
+    /// expiry outside the rules like `Ultimate`
+    pub(crate) fn set_expiring(&mut self, tile: u16) {
+        self.expiring = self.array[tile as usize];
+        self.set(tile, Cell::E);
+    }
+
+    /// private function. Finds the mark that will
+    /// be removed when the next move is made, see
+    /// `Rules::expiring_move`, and marks it as
+    /// Expiring
+    fn mark_expiring(&mut self) {
+        self.expiring = 0;
+        if let Some(oldest) = self.rules.expiring_move(self.last, &self.array) {
+            let index = self.array.iter().position(|&n| n == oldest).unwrap() as u16;
+            self.set(index, Cell::E);
+            self.expiring = oldest;
+        }
+    }
+
+    /// the tiles the player to move can play on,
+    /// see `Rules::legal_moves`
+    pub fn legal_moves(&self) -> Vec<u16> {
+        self.rules.legal_moves(self)
+    }
+
+    /// every complete line on the board, expiring
+    /// marks do not count. In a legal position they
+    /// all go through the tile of the last move
+    pub fn winning_lines(&self) -> Vec<Vec<u16>> {
+        self.rules.winning_lines(self)
+    }
+
+    /// true once no more moves can be made, see
+    /// `Rules::is_over`
+    pub fn is_over(&self) -> bool {
+        self.rules.is_over(self)
+    }
+
+    /// in progress, won or drawn, see
+    /// `Rules::status`
+    pub fn status(&self) -> Status {
+        self.rules.status(self)
+    }
+
+    /// the winner together with the lines they
+    /// completed, or None if no one has won.
+    /// Marks that fall with gravity can complete
+    /// lines of both players at once, the player
+    /// who moved wins then
+    pub fn win(&self) -> Option<Win> {
+        let mut lines = self.winning_lines();
+        let mover = self.rules.owner(self.last);
+        let winner = if lines.iter().any(|line| self.get(line[0]) == mover) {
+            mover
+        } else {
+            self.get(*lines.first()?.first()?)
+        };
+        lines.retain(|line| self.get(line[0]) == winner);
+        Some(Win { winner, lines })
+    }
+
+    /// the player who has a complete line on the
+    /// board, or None. Expiring marks do not count
+    pub fn winner(&self) -> Cell {
+        self.win().map_or(Cell::N, |win| win.winner)
     }
     
     /// function used for making a move on the 
-    /// grid. a move is only legal if it is on a 
-    /// None Cell, otherwise it will panic.
+    /// grid. a move is only legal if the rules
+    /// allow it, see `Rules::check_move`,
+    /// otherwise the reason is returned as an error.
     /// Returns the new state without modifying
     /// the current state.
     /// 
-    /// The logic for moving the expiring and latest
-    /// move is also included here but may be 
-    /// extracted to a function of its own if needed
-    pub fn turn(&self, i: u16) -> Option<Self> {
-        if  Cell::N != self.get(i) {
-            return None;
-        }
-
+    /// The mark that was expiring is removed and
+    /// the next one to expire is found according
+    /// to the expiry window of the rules. With
+    /// gravity the marks above the removed one
+    /// fall down, and can complete lines as well
+    pub fn turn(&self, i: u16) -> Result<Self, GameError> {
+        self.rules.check_move(self, i)?;
         let mut new = self.clone();
+        if new.last >= RENUMBER_AT {
+            new.renumber();
+        }
 
         new.last += 1;
-        let cell = if new.last % 2 == 0 {
-            Cell::X
-        } else {
-            Cell::O
-        };
+        let cell = new.rules.owner(new.last);
         new.array[i as usize] = new.last;
         new.set(i, cell);
 
         if new.expiring != 0 {
             let index = new.array
             .iter()
-            .enumerate()
-            .find(|&x| *x.1 == new.expiring)
-            .unwrap()
-            .0 as u16;
+            .position(|&n| n == new.expiring)
+            .ok_or(GameError::CorruptNumbering)? as u16;
 
             new.set(index, Cell::N);
             new.array[index as usize] = 0;
-            
-            new.expiring += 1;
-            let index = new.array
-            .iter()
-            .enumerate()
-            .find(|&x| *x.1 == new.expiring)
-            .unwrap()
-            .0 as u16;
+            if new.rules.gravity {
+                new.settle(new.board().column(index));
+            }
+        }
+        new.mark_expiring();
 
-            new.set(index, Cell::E);
-        } else 
-        
-        if new.last == 6 {
-            new.expiring += 1;
-            let index = new.array
+        Ok(new)
+    }
+
+    /// drops a mark in the column for the player
+    /// to move, see `Rules::drop_tile`. Otherwise
+    /// the same as `turn`
+    pub fn drop_mark(&self, column: u16) -> Result<Self, GameError> {
+        let tile = self.rules.drop_tile(self, column).ok_or(GameError::ColumnFull(column))?;
+        self.turn(tile)
+    }
+
+    /// private function. Lets the marks of a
+    /// column fall down onto each other, after
+    /// a mark below them has been removed
+    fn settle(&mut self, column: u16) {
+        let tiles = self.board().column_tiles(column);
+        let mut numbers: Vec<u16> = tiles.iter().map(|&tile| self.array[tile as usize]).filter(|&n| n != 0).collect();
+        numbers.resize(tiles.len(), 0);
+        for (&tile, &n) in tiles.iter().zip(&numbers) {
+            self.array[tile as usize] = n;
+            self.set(tile, self.rules.owner(n));
+        }
+    }
+
+    /// the position in a compact notation like
+    /// `.O./XX./X.e x 17`: the rows from the top
+    /// separated by `/` with `.` for an empty tile
+    /// and `e` for the expiring mark, the side to
+    /// move and the number of the next move.
+    /// The layers of a cubic board are separated
+    /// by `|`, the top one first.
+    /// When the order of the marks does not follow
+    /// from reading the board, their tiles are
+    /// added oldest first, like `b1,a2,b2,a3,c3,a1`
+    pub fn to_notation(&self) -> String {
+        let cells: Vec<Cell> = self.state.iter().flatten().copied().collect();
+        let rows: Vec<String> = self.state
+        .iter()
+        .map(|row| row.iter().map(|&cell| symbol(cell)).collect())
+        .collect();
+        let layers: Vec<String> = rows
+        .chunks(self.board().height as usize)
+        .map(|layer| layer.join("/"))
+        .collect();
+        let side = if self.to_move() == Player::X { 'x' } else { 'o' };
+        let mut text = format!("{} {} {}", layers.join("|"), side, u32::from(self.last) + 1);
+
+        if number_marks(self.rules, &cells, self.last, None).as_deref() != Ok(&self.array[..]) {
+            let mut marks: Vec<(u16, u16)> = self.array
             .iter()
             .enumerate()
-            .find(|&x| *x.1 == new.expiring)
-            .unwrap()
-            .0 as u16;
+            .filter(|&(_, &n)| n != 0)
+            .map(|(tile, &n)| (n, tile as u16))
+            .collect();
+            marks.sort_unstable();
+            let order: Vec<String> = marks.iter().map(|&(_, tile)| tile_name(self.board(), tile)).collect();
+            text.push(' ');
+            text.push_str(&order.join(","));
+        }
+        text
+    }
+
+    /// reads a position written by `to_notation`
+    /// for the given rules. The position must be
+    /// one that can be reached by playing, see
+    /// `validate`
+    pub fn from_notation(rules: Rules, text: &str) -> Result<Self, NotationError> {
+        let bad_position = || NotationError::BadPosition(text.to_string());
+        let fields: Vec<&str> = text.split_whitespace().collect();
+        let (grid, side, next, order) = match fields[..] {
+            [grid, side, next] => (grid, side, next, None),
+            [grid, side, next, order] => (grid, side, next, Some(order)),
+            _ => return Err(bad_position()),
+        };
 
-            new.set(index, Cell::E);
+        let board = rules.board;
+        let layers: Vec<&str> = grid.split('|').collect();
+        if layers.len() != board.depth as usize || layers.iter().any(|layer| layer.split('/').count() != board.height as usize) {
+            return Err(bad_position());
         }
+        let rows: Vec<&str> = layers.iter().flat_map(|layer| layer.split('/')).collect();
+        if rows.iter().any(|row| row.chars().count() != board.width as usize) {
+            return Err(bad_position());
+        }
+        let cells: Vec<Cell> = rows
+        .iter()
+        .flat_map(|row| row.chars())
+        .map(|c| match c {
+            '.' => Some(Cell::N),
+            'X' => Some(Cell::X),
+            'O' => Some(Cell::O),
+            'e' => Some(Cell::E),
+            _ => None,
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(bad_position)?;
+
+        let next: u16 = next.parse().map_err(|_| bad_position())?;
+        if next == 0 {
+            return Err(bad_position());
+        }
+        let order = match order {
+            Some(order) => Some(
+                order
+                .split(',')
+                .map(|name| parse_tile(&board, name))
+                .collect::<Option<Vec<u16>>>()
+                .ok_or_else(bad_position)?
+            ),
+            None => None,
+        };
 
-        Some(new)
+        let array = number_marks(rules, &cells, next - 1, order.as_deref())
+        .map_err(NotationError::InvalidPosition)?;
+        let state = GameState::try_with_rules(rules, &array).map_err(NotationError::InvalidPosition)?;
+        let to_move = match side {
+            "x" => Player::X,
+            "o" => Player::O,
+            _ => return Err(bad_position()),
+        };
+        if state.to_move() != to_move || state.state.iter().flatten().ne(cells.iter()) {
+            return Err(bad_position());
+        }
+        Ok(state)
     }
 }
 
+/// the grid one row per line as in the notation,
+/// followed by the side to move and the move number
+impl fmt::Display for GameState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, row) in self.state.iter().enumerate() {
+            // a blank line between the layers
+            if i > 0 && i % self.board().height as usize == 0 {
+                writeln!(f)?;
+            }
+            let cells: Vec<String> = row.iter().map(|&cell| symbol(cell).to_string()).collect();
+            writeln!(f, "{}", cells.join(" "))?;
+        }
+        write!(f, "{:?} to move, move {}", self.to_move(), u32::from(self.last) + 1)
+    }
+}
+
+/// private function. The character of a cell
+/// in the position notation
+fn symbol(cell: Cell) -> char {
+    match cell {
+        Cell::X => 'X',
+        Cell::O => 'O',
+        Cell::E => 'e',
+        Cell::N => '.',
+    }
+}
+
+/// private function. Gives the marks of the
+/// notation their move numbers, the ones
+/// `Rules::live_moves` keeps after move `last`.
+/// Without an order the expiring mark is the one
+/// marked `e` and every other move goes on the
+/// first free tile in reading order with its mark
+fn number_marks(rules: Rules, cells: &[Cell], last: u16, order: Option<&[u16]>) -> Result<Vec<u16>, GameError> {
+    let live = rules.live_moves(last);
+    let marked: Vec<u16> = (0..cells.len() as u16).filter(|&i| cells[i as usize] != Cell::N).collect();
+    if marked.len() > live.len() {
+        return Err(GameError::TooManyMarks { count: marked.len(), window: rules.expiry.window() });
+    }
+    let expiring = rules.expiring_move(last, &live);
+
+    let mut array = vec![0; cells.len()];
+    for (i, &n) in live.iter().enumerate() {
+        let fits = |tile: u16| match cells[tile as usize] {
+            Cell::E => Some(n) == expiring,
+            cell => cell == rules.owner(n) && Some(n) != expiring,
+        };
+        let tile = match order {
+            Some(order) => order.get(i).copied().filter(|&tile| (tile as usize) < cells.len() && fits(tile)),
+            None => marked.iter().copied().find(|&tile| array[tile as usize] == 0 && fits(tile)),
+        };
+        match tile {
+            Some(tile) if array[tile as usize] == 0 => array[tile as usize] = n,
+            Some(tile) => return Err(GameError::DuplicateMove(array[tile as usize])),
+            None => return Err(GameError::MissingMove(n)),
+        }
+    }
+    if order.map_or(false, |order| order.len() != live.len()) {
+        return Err(GameError::TooManyMarks { count: order.map_or(0, <[u16]>::len), window: rules.expiry.window() });
+    }
+    Ok(array)
+}
+impl TryFrom<&[u16]> for GameState {
+    type Error = GameError;
+
+    /// validated parsing of an array for the
+    /// default 3x3 game
+    fn try_from(array: &[u16]) -> Result<Self, Self::Error> {
+        GameState::try_with_rules(Rules::default(), array)
+    }
+}
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 
 
@@ -185,7 +1170,12 @@ impl GameState {
 
 #[cfg(test)]
 mod test {
-    use super::{Cell, GameState};
+    use std::collections::VecDeque;
+    use std::convert::TryFrom;
+
+    use super::{Board, Cell, Expiry, GameState, Player, Rules, Status, Win, RENUMBER_AT};
+    use crate::error::GameError;
+    use crate::notation::NotationError;
 
     #[test]
     /// Parsing a game state that has an expiring move in it
@@ -353,7 +1343,7 @@ mod test {
 
     #[test]
     /// tests the handling of an illegal turn by
-    /// returning None
+    /// returning the reason
     fn game_state_illegal_turn() {
         let array = [
             0, 15, 0,
@@ -362,7 +1352,671 @@ mod test {
         ];
 
         let game_state = GameState::from_array(array);
-        let game_state = game_state.turn(1);
-        assert_eq!(game_state, None);
+        assert_eq!(game_state.turn(1), Err(GameError::OccupiedCell(1)));
+        assert_eq!(game_state.turn(4), Err(GameError::ExpiringCell(4)));
+        assert_eq!(game_state.turn(9), Err(GameError::OutOfBounds(9)));
+    }
+
+    #[test]
+    /// no more moves once someone has a line
+    fn game_state_turn_after_win() {
+        let array = [
+            2, 1, 0,
+            4, 3, 0,
+            6, 0, 5,
+        ];
+
+        let game_state = GameState::from_array(array);
+        assert_eq!(game_state.turn(2), Err(GameError::GameWon(Cell::X)));
+    }
+
+    #[test]
+    /// an array changed behind the state's back
+    /// is reported instead of panicking
+    fn game_state_turn_corrupt() {
+        let array = [
+            0, 15, 0,
+            14, 11, 0,
+            12, 16, 13,
+        ];
+
+        let mut game_state = GameState::from_array(array);
+        game_state.array[4] = 0;
+        assert_eq!(game_state.turn(0), Err(GameError::CorruptNumbering));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn cell_numbers() {
+        for cell in [Cell::X, Cell::O, Cell::N] {
+            assert_eq!(Cell::from_number(cell.number()), cell);
+            assert_eq!(Cell::cell(cell.number()), cell);
+        }
+    }
+
+    #[test]
+    /// the classic board has exactly the eight
+    /// rows, columns and diagonals
+    fn board_lines_default() {
+        let mut lines = Board::default().lines();
+        lines.sort();
+        let expected: Vec<Vec<u16>> = vec![
+            vec![0, 1, 2],
+            vec![0, 3, 6],
+            vec![0, 4, 8],
+            vec![1, 4, 7],
+            vec![2, 4, 6],
+            vec![2, 5, 8],
+            vec![3, 4, 5],
+            vec![6, 7, 8],
+        ];
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    /// 4x4 board with 3 in a row has two segments
+    /// per row and column and eight diagonals
+    fn board_lines_4x4() {
+        let lines = Board::square(4, 3).lines();
+        assert_eq!(lines.len(), 24);
+        assert!(lines.contains(&vec![1, 6, 11]));
+        assert!(lines.contains(&vec![7, 10, 13]));
+    }
+
+    #[test]
+    /// the 3x3x3 cube has 49 lines and the 4x4x4
+    /// one 76, none of them twice
+    fn board_lines_cube() {
+        for (size, count) in [(3, 49), (4, 76)] {
+            let mut lines = Board::cube(size, size).lines();
+            assert_eq!(lines.len(), count);
+            for line in lines.iter_mut() {
+                line.sort_unstable();
+            }
+            lines.sort();
+            lines.dedup();
+            assert_eq!(lines.len(), count);
+        }
+        // straight down and the space diagonals
+        let lines = Board::cube(3, 3).lines();
+        assert!(lines.contains(&vec![4, 13, 22]));
+        assert!(lines.contains(&vec![0, 13, 26]));
+        assert!(lines.contains(&vec![8, 13, 18]));
+    }
+
+    #[test]
+    fn board_coordinates() {
+        let board = Board::cube(4, 4);
+        assert_eq!(board.coordinates(0), (0, 0, 0));
+        assert_eq!(board.coordinates(22), (2, 1, 1));
+        for tile in 0..board.len() as u16 {
+            let (column, row, layer) = board.coordinates(tile);
+            assert_eq!(board.tile(column, row, layer), Some(tile));
+        }
+        assert_eq!(board.tile(4, 0, 0), None);
+        assert_eq!(board.tile(0, 0, 4), None);
+        assert_eq!(Board::square(4, 3).coordinates(7), (3, 1, 0));
+    }
+
+    #[test]
+    fn game_state_win_line() {
+        let array = [
+            2, 1, 0,
+            4, 3, 0,
+            6, 0, 5,
+        ];
+        let game_state = GameState::from_array(array);
+        let expected = Win { winner: Cell::X, lines: vec![vec![0, 3, 6]] };
+        assert_eq!(game_state.win(), Some(expected));
+        assert_eq!(GameState::new().win(), None);
+    }
+
+    #[test]
+    /// one move can complete a row and a column
+    /// at the same time
+    fn game_state_win_two_lines() {
+        let rules = Rules::new(Board::square(4, 3), Expiry::Global(10));
+        let array = vec![
+            2, 4, 10, 0,
+            1, 3, 6, 0,
+            5, 7, 8, 9,
+            0, 0, 0, 0,
+        ];
+        let game_state = GameState::with_rules(rules, array);
+        let win = game_state.win().unwrap();
+        assert_eq!(win.winner, Cell::X);
+        assert_eq!(win.lines, vec![vec![0, 1, 2], vec![2, 6, 10]]);
+        assert!(game_state.validate().is_ok());
+    }
+
+    #[test]
+    /// winning on a larger board with 4 in a row
+    fn game_state_winner_5x5() {
+        let array = vec![
+            0, 0, 0, 0, 0,
+            0, 2, 0, 0, 0,
+            0, 1, 4, 0, 0,
+            0, 0, 3, 6, 0,
+            0, 0, 5, 0, 8,
+        ];
+        let game_state = GameState::with_board(Board::square(5, 4), array);
+        assert_eq!(game_state.winner(), Cell::X);
+    }
+
+    #[test]
+    fn game_state_turn_4x4() {
+        let array = vec![
+            0, 0, 0, 0,
+            0, 1, 0, 0,
+            0, 0, 2, 0,
+            0, 0, 0, 0,
+        ];
+        let game_state = GameState::with_board(Board::square(4, 3), array);
+        let game_state = game_state.turn(15).unwrap();
+
+        assert_eq!(game_state.array[15], 3);
+        assert_eq!(game_state.get(15), Cell::O);
+        assert_eq!(game_state.last, 3);
+    }
+
+    #[test]
+    /// a global window of 8 keeps the first mark
+    /// until the eighth move is on the board
+    fn game_state_global_window() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::Global(8));
+        let array = vec![
+            1, 2, 3, 0,
+            4, 5, 6, 0,
+            7, 0, 0, 0,
+            0, 0, 0, 0,
+        ];
+        let game_state = GameState::with_rules(rules, array);
+        assert_eq!(game_state.expiring, 0);
+
+        let game_state = game_state.turn(15).unwrap();
+        assert_eq!(game_state.expiring, 1);
+        assert_eq!(game_state.get(0), Cell::E);
+
+        let game_state = game_state.turn(14).unwrap();
+        assert_eq!(game_state.array[0], 0);
+        assert_eq!(game_state.expiring, 2);
+        assert_eq!(game_state.get(1), Cell::E);
+    }
+
+    #[test]
+    /// with a per player window only the marks of
+    /// the player about to move can be expiring
+    fn game_state_per_player_window() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::PerPlayer(4));
+        let array = vec![
+            1, 2, 3, 0,
+            4, 5, 6, 0,
+            7, 0, 0, 0,
+            0, 0, 0, 0,
+        ];
+        // X moves next and only has three marks
+        let game_state = GameState::with_rules(rules, array);
+        assert_eq!(game_state.expiring, 0);
+
+        // O moves next with four marks, 1 goes next
+        let game_state = game_state.turn(15).unwrap();
+        assert_eq!(game_state.expiring, 1);
+
+        // X moves next with four marks, 2 goes next
+        let game_state = game_state.turn(14).unwrap();
+        assert_eq!(game_state.array[0], 0);
+        assert_eq!(game_state.expiring, 2);
+        assert_eq!(game_state.get(1), Cell::E);
+    }
+
+    #[test]
+    /// three marks each is the same game as the
+    /// default global window of six
+    fn game_state_per_player_matches_default() {
+        let rules = Rules::new(Board::default(), Expiry::PerPlayer(3));
+        let mut default = GameState::new();
+        let mut per_player = GameState::empty(rules);
+
+        for tile in [4, 0, 8, 2, 1, 7, 6, 3, 5] {
+            default = default.turn(tile).unwrap();
+            per_player = per_player.turn(tile).unwrap();
+            assert_eq!(default.array, per_player.array);
+            assert_eq!(default.state, per_player.state);
+        }
+    }
+
+    #[test]
+    fn game_state_validate_ok() {
+        let array: &[u16] = &[
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 0,
+        ];
+        assert!(GameState::try_from(array).is_ok());
+        assert!(GameState::try_from(&[0u16; 9][..]).is_ok());
+    }
+
+    #[test]
+    /// every kind of corrupted array is reported
+    /// with what is wrong about it
+    fn game_state_validate_errors() {
+        let cases: [(&[u16], GameError); 5] = [
+            (&[0, 15, 0, 13], GameError::WrongLength { expected: 9, found: 4 }),
+            (&[0, 15, 0, 13, 13, 14, 12, 16, 0], GameError::DuplicateMove(13)),
+            (&[1, 2, 3, 4, 5, 6, 7, 0, 0], GameError::TooManyMarks { count: 7, window: 6 }),
+            (&[0, 15, 0, 13, 10, 14, 12, 16, 0], GameError::StaleMove(10)),
+            (&[0, 15, 0, 13, 0, 14, 12, 16, 0], GameError::MissingMove(11)),
+        ];
+        for (array, expected) in cases {
+            assert_eq!(GameState::try_from(array), Err(expected));
+        }
+
+        // O's line was complete before move 7
+        let rules = Rules::new(Board::square(4, 3), Expiry::Global(8));
+        let array = [
+            1, 3, 5, 0,
+            0, 0, 0, 4,
+            0, 2, 0, 0,
+            0, 0, 6, 7,
+        ];
+        assert_eq!(GameState::try_with_rules(rules, &array), Err(GameError::UnreachableWin));
     }
-}
\ No newline at end of file
+
+    #[test]
+    /// each player keeps their own most recent
+    /// marks with a per player window
+    fn game_state_validate_per_player() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::PerPlayer(2));
+        let array = [
+            0, 0, 7, 0,
+            8, 0, 0, 0,
+            0, 10, 0, 0,
+            0, 0, 9, 0,
+        ];
+        assert!(GameState::try_with_rules(rules, &array).is_ok());
+
+        let array = [
+            0, 0, 7, 0,
+            8, 0, 0, 0,
+            0, 10, 0, 0,
+            0, 0, 5, 0,
+        ];
+        assert_eq!(GameState::try_with_rules(rules, &array), Err(GameError::StaleMove(5)));
+    }
+
+    #[test]
+    /// with X starting every mark changes sides
+    /// but the expiry stays the same
+    fn game_state_x_first() {
+        let array = [
+            0, 3, 0,
+            4, 6, 5,
+            0, 7, 2
+        ];
+        let rules = Rules::default().with_first(Player::X);
+        let o_first = GameState::from_array(array);
+        let x_first = GameState::with_rules(rules, array.to_vec());
+
+        assert_eq!(x_first.get(1), Cell::X);
+        assert_eq!(x_first.get(4), Cell::O);
+        assert_eq!(x_first.get(8), Cell::E);
+        assert_eq!(o_first.to_move(), Player::X);
+        assert_eq!(x_first.to_move(), Player::O);
+
+        let x_first = GameState::empty(rules).turn(4).unwrap();
+        assert_eq!(x_first.get(4), Cell::X);
+    }
+
+    #[test]
+    fn rules_sized() {
+        let rules = Rules::sized(4, 4, 3, Some(3), Some(true), Some(1));
+        assert_eq!(rules, Rules::new(Board::square(4, 3), Expiry::PerPlayer(3)).with_first(Player::X));
+        assert_eq!((rules.width(), rules.height(), rules.win_length()), (4, 4, 3));
+        assert_eq!((rules.window(), rules.per_player(), rules.first()), (3, true, 1));
+        assert_eq!(Rules::sized(3, 3, 3, None, None, None), Rules::infinite());
+    }
+
+    #[test]
+    /// the rules decide every move of a game
+    fn rules_policies() {
+        let rules = Rules::default();
+        let game_state = GameState::from_array([
+            1, 3, 0,
+            2, 4, 0,
+            0, 0, 0,
+        ]);
+        assert_eq!(rules.legal_moves(&game_state), vec![2, 5, 6, 7, 8]);
+        assert_eq!(rules.check_move(&game_state, 2), Ok(()));
+        assert_eq!(rules.check_move(&game_state, 0), Err(GameError::OccupiedCell(0)));
+        assert_eq!(rules.check_move(&game_state, 9), Err(GameError::OutOfBounds(9)));
+        assert!(!rules.is_over(&game_state));
+
+        let won = game_state.turn(2).unwrap();
+        assert_eq!(rules.winning_lines(&won), vec![vec![0, 1, 2]]);
+        assert!(rules.legal_moves(&won).is_empty());
+        assert_eq!(rules.check_move(&won, 8), Err(GameError::GameWon(Cell::O)));
+        assert!(won.is_over());
+    }
+
+    #[test]
+    /// classic marks never expire and a full
+    /// board without a line is a draw
+    fn game_state_classic_draw() {
+        let rules = Rules::classic();
+        let mut game_state = GameState::empty(rules);
+        for (i, &tile) in [4, 0, 2, 6, 3, 5, 7, 1, 8].iter().enumerate() {
+            assert_eq!(game_state.status(), Status::InProgress, "after {} moves", i);
+            assert_eq!(game_state.expiring, 0);
+            game_state = game_state.turn(tile).unwrap();
+        }
+        assert_eq!(game_state.array, vec![2, 8, 3, 5, 1, 6, 4, 7, 9]);
+        assert_eq!(game_state.status(), Status::Drawn);
+        assert_eq!(game_state.status().number(), 2);
+        assert!(game_state.legal_moves().is_empty());
+        assert_eq!(game_state.turn(0), Err(GameError::OccupiedCell(0)));
+        assert_eq!(game_state.validate(), Ok(()));
+        // the same moves with expiring marks go on
+        let infinite = GameState::with_rules(Rules::default(), game_state.array.clone());
+        assert_eq!(infinite.validate(), Err(GameError::TooManyMarks { count: 9, window: 6 }));
+    }
+
+    #[test]
+    fn game_state_status() {
+        let won = GameState::from_array([
+            1, 3, 5,
+            2, 4, 0,
+            0, 0, 0,
+        ]);
+        assert_eq!(won.status(), Status::Won(Player::O));
+        assert_eq!(won.status().number(), -1);
+        let classic = GameState::with_rules(Rules::classic(), won.array.clone());
+        assert_eq!(classic.status(), Status::Won(Player::O));
+        assert_eq!(classic.get(0), Cell::O);
+        assert_eq!(GameState::empty(Rules::classic()).status().number(), 0);
+    }
+
+    #[test]
+    fn board_columns() {
+        let board = Board::square(4, 3);
+        assert_eq!(board.columns(), 4);
+        assert_eq!(board.column_tiles(1), vec![13, 9, 5, 1]);
+        assert_eq!(board.column(6), 2);
+        assert!(board.column_tiles(4).is_empty());
+        let cube = Board::cube(3, 3);
+        assert_eq!(cube.columns(), 9);
+        assert_eq!(cube.column_tiles(4), vec![16, 13, 10]);
+        assert_eq!(cube.column(13), 4);
+    }
+
+    #[test]
+    /// marks land on the lowest free tile, and
+    /// the marks above an expired one fall down
+    fn game_state_gravity() {
+        let rules = Rules::new(Board::square(4, 3), Expiry::Global(4)).with_gravity();
+        let mut game_state = GameState::empty(rules);
+        for column in [0, 0, 1, 3] {
+            game_state = game_state.drop_mark(column).unwrap();
+        }
+        assert_eq!(game_state.array[8], 2);
+        assert_eq!(game_state.get(12), Cell::E);
+
+        let game_state = game_state.drop_mark(2).unwrap();
+        assert_eq!(&game_state.array[8..], &[0, 0, 0, 0, 2, 3, 5, 4]);
+        assert_eq!(game_state.get(12), Cell::E);
+        assert_eq!(game_state.legal_moves(), vec![8, 9, 10, 11]);
+        assert_eq!(game_state.turn(4), Err(GameError::FloatingTile(4)));
+        assert_eq!(game_state.validate(), Ok(()));
+
+        let mut full = GameState::empty(Rules::classic().with_gravity());
+        for _ in 0..3 {
+            full = full.drop_mark(1).unwrap();
+        }
+        assert_eq!(full.drop_mark(1), Err(GameError::ColumnFull(1)));
+        assert_eq!(full.drop_mark(3), Err(GameError::ColumnFull(3)));
+        assert_eq!(full.rules.drop_tile(&full, 0), Some(6));
+    }
+
+    #[test]
+    /// a mark falling onto the bottom row can win
+    /// the game for the player who did not move
+    fn game_state_gravity_fall_wins() {
+        let rules = Rules::new(Board::new(5, 3, 3), Expiry::Global(8)).with_gravity();
+        let mut game_state = GameState::empty(rules);
+        for column in [0, 4, 3, 1, 3, 2, 4, 0] {
+            game_state = game_state.drop_mark(column).unwrap();
+        }
+        assert_eq!(game_state.winner(), Cell::N);
+        assert_eq!(game_state.get(10), Cell::E);
+        let game_state = game_state.drop_mark(1).unwrap();
+        assert_eq!(game_state.array[10], 8);
+        assert_eq!(game_state.winning_lines(), vec![vec![10, 11, 12]]);
+        assert_eq!(game_state.status(), Status::Won(Player::X));
+        assert_eq!(game_state.validate(), Ok(()));
+    }
+
+    #[test]
+    fn game_state_gravity_validate() {
+        let rules = Rules::classic().with_gravity();
+        let floating = [
+            0, 0, 0,
+            1, 0, 0,
+            0, 2, 0,
+        ];
+        let game_state = GameState::with_rules(rules, floating.to_vec());
+        assert_eq!(game_state.validate(), Err(GameError::FloatingTile(3)));
+        assert_eq!(GameState::try_with_rules(Rules::classic(), &floating).map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    /// a line through the layers wins on a cube,
+    /// with expiring and classic rules
+    fn game_state_cube() {
+        let rules = Rules::cube(3, None, None, None);
+        for rules in [rules, rules.without_expiry()] {
+            let mut game_state = GameState::empty(rules);
+            for tile in [0, 1, 13, 2, 26] {
+                game_state = game_state.turn(tile).unwrap();
+            }
+            assert_eq!(game_state.winning_lines(), vec![vec![0, 13, 26]]);
+            assert_eq!(game_state.status(), Status::Won(Player::O));
+            assert_eq!(game_state.get(13), Cell::O);
+
+            let text = "OXX/.../...|.../.O./...|.../.../..O x 6";
+            assert_eq!(game_state.to_notation(), text);
+            assert_eq!(GameState::from_notation(rules, text), Ok(game_state));
+        }
+        assert!(GameState::from_notation(rules, "OXX/.../... x 4").is_err());
+    }
+
+    #[test]
+    fn game_state_notation() {
+        let game_state = GameState::from_notation(Rules::default(), "X../.O./... o 3").unwrap();
+        assert_eq!(game_state.array, vec![2, 0, 0, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(game_state.to_notation(), "X../.O./... o 3");
+
+        // the marks of X are not numbered in
+        // reading order, so their order is written
+        let game_state = GameState::from_array([
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 0,
+        ]);
+        let text = ".O./OeX/XX. o 17 b2,a3,a2,c2,b1,b3";
+        assert_eq!(game_state.to_notation(), text);
+        assert_eq!(GameState::from_notation(Rules::default(), text), Ok(game_state));
+        assert_eq!(GameState::empty(Rules::default()).to_notation(), ".../.../... o 1");
+    }
+
+    #[test]
+    /// every position of long games reads back
+    /// to the same state
+    fn game_state_notation_round_trip() {
+        let rules = [
+            Rules::default(),
+            Rules::new(Board::square(4, 3), Expiry::PerPlayer(3)).with_first(Player::X),
+            Rules::new(Board::new(5, 3, 3), Expiry::Global(5)),
+            Rules::classic(),
+        ];
+        for rules in rules {
+            let mut game_state = GameState::empty(rules);
+            for i in 0..200_usize {
+                let text = game_state.to_notation();
+                assert_eq!(GameState::from_notation(rules, &text), Ok(game_state.clone()), "{}", text);
+
+                let moves = game_state.legal_moves();
+                if moves.is_empty() {
+                    game_state = GameState::empty(rules);
+                    continue;
+                }
+                game_state = game_state.turn(moves[i * 7 % moves.len()]).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn game_state_notation_errors() {
+        let rules = Rules::default();
+        let bad = |text: &str| Err(NotationError::BadPosition(text.to_string()));
+        assert_eq!(GameState::from_notation(rules, "X../.O. o 3"), bad("X../.O. o 3"));
+        assert_eq!(GameState::from_notation(rules, "X../.Z./... o 3"), bad("X../.Z./... o 3"));
+        assert_eq!(GameState::from_notation(rules, "X../.O./... x 3"), bad("X../.O./... x 3"));
+        assert_eq!(GameState::from_notation(rules, "X../.O./..."), bad("X../.O./..."));
+        assert_eq!(
+            GameState::from_notation(rules, "XX./.O./... o 3"),
+            Err(NotationError::InvalidPosition(GameError::TooManyMarks { count: 3, window: 6 })),
+        );
+        assert_eq!(
+            GameState::from_notation(rules, "e../.O./... o 3"),
+            Err(NotationError::InvalidPosition(GameError::MissingMove(2))),
+        );
+        assert_eq!(
+            GameState::from_notation(rules, "X../OO./... x 4 b2,a1,b2"),
+            Err(NotationError::InvalidPosition(GameError::DuplicateMove(1))),
+        );
+    }
+
+    #[test]
+    fn game_state_display() {
+        let game_state = GameState::from_notation(Rules::default(), "X../.O./... o 3").unwrap();
+        assert_eq!(game_state.to_string(), "X . .\n. O .\n. . .\nO to move, move 3");
+    }
+
+    #[test]
+    /// the same position far into a game has the
+    /// same marks and expiring mark after renumbering
+    fn game_state_renumber() {
+        let array = [
+            0, 60015, 0,
+            60013, 60011, 60014,
+            60012, 60016, 0,
+        ];
+        let mut game_state = GameState::from_array(array);
+        game_state.renumber();
+        assert_eq!(game_state.array, vec![0, 5, 0, 3, 1, 4, 2, 6, 0]);
+        assert_eq!(game_state.last, 6);
+        assert_eq!(game_state.expiring, 1);
+        assert_eq!(game_state, GameState::from_array([0, 5, 0, 3, 1, 4, 2, 6, 0]));
+
+        // the first mark belongs to X, so it stays even
+        let mut game_state = GameState::from_array([0, 60016, 0, 0, 0, 0, 0, 60017, 60018]);
+        game_state.renumber();
+        assert_eq!(game_state.array, vec![0, 2, 0, 0, 0, 0, 0, 3, 4]);
+    }
+
+    #[test]
+    /// arrays with move numbers that can not go up
+    /// any further are renumbered when parsed
+    fn game_state_renumber_high_array() {
+        let array = [
+            0, u16::MAX - 2, 0,
+            u16::MAX - 4, u16::MAX - 6, u16::MAX - 3,
+            u16::MAX - 5, u16::MAX - 1, u16::MAX,
+        ];
+        let game_state = GameState::try_from(&array[1..]).err();
+        assert_eq!(game_state, Some(GameError::WrongLength { expected: 9, found: 8 }));
+
+        let game_state = GameState::with_rules(Rules::default(), array.to_vec());
+        assert!(game_state.last < RENUMBER_AT);
+        assert_eq!(game_state.get(4), Cell::E);
+        assert_eq!(game_state.to_move(), Player::X);
+        assert_eq!(game_state.validate(), Err(GameError::TooManyMarks { count: 7, window: 6 }));
+
+        let mut array = array;
+        array[4] = 0;
+        let game_state = GameState::try_with_rules(Rules::default(), &array).unwrap();
+        let next = game_state.turn(0).unwrap();
+        assert_eq!(next.get(0), Cell::X);
+        assert_eq!(next.get(6), Cell::N);
+        assert_eq!(next.get(3), Cell::E);
+    }
+
+    /// plays `moves` moves without anyone winning
+    /// and checks every position against a plain
+    /// list of the marks on the board, oldest first
+    fn play_long_game(rules: Rules, moves: usize) {
+        let mut game_state = GameState::empty(rules);
+        let mut marks: VecDeque<(u16, Player)> = VecDeque::new();
+        let window = rules.expiry.window() as usize;
+
+        for i in 0..moves {
+            let player = game_state.to_move();
+            let legal = game_state.legal_moves();
+            let start = (i * 7 + i / 3) % legal.len();
+            let (tile, next) = (0..legal.len())
+            .map(|j| legal[(start + j) % legal.len()])
+            .find_map(|tile| {
+                let next = game_state.turn(tile).unwrap();
+                (next.winner() == Cell::N).then_some((tile, next))
+            })
+            .unwrap_or_else(|| panic!("no move without a win after {} moves", i));
+            game_state = next;
+
+            marks.push_back((tile, player));
+            let own = |marks: &VecDeque<(u16, Player)>, player: Player| {
+                marks.iter().position(|&(_, p)| p == player)
+            };
+            let removed = match rules.expiry {
+                Expiry::Global(_) if marks.len() > window => Some(0),
+                Expiry::PerPlayer(_) if marks.iter().filter(|&&(_, p)| p == player).count() > window => own(&marks, player),
+                _ => None,
+            };
+            if let Some(index) = removed {
+                marks.remove(index);
+            }
+
+            let next = player.other();
+            let expiring = match rules.expiry {
+                Expiry::Global(_) if marks.len() == window => Some(0),
+                Expiry::PerPlayer(_) if marks.iter().filter(|&&(_, p)| p == next).count() == window => own(&marks, next),
+                _ => None,
+            }.map(|index| marks[index].0);
+
+            for tile in 0..rules.board.len() as u16 {
+                let expected = match marks.iter().find(|&&(t, _)| t == tile) {
+                    Some(_) if Some(tile) == expiring => Cell::E,
+                    Some(&(_, p)) => p.cell(),
+                    None => Cell::N,
+                };
+                assert_eq!(game_state.get(tile), expected, "tile {} after {} moves", tile, i + 1);
+            }
+            assert!(game_state.last <= RENUMBER_AT);
+        }
+        assert_eq!(game_state.validate(), Ok(()));
+    }
+
+    #[test]
+    fn game_state_long_game() {
+        play_long_game(Rules::new(Board::square(4, 4), Expiry::Global(6)), 100_000);
+    }
+
+    #[test]
+    fn game_state_long_game_per_player() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::PerPlayer(3)).with_first(Player::X);
+        play_long_game(rules, 100_000);
+    }
+
+    #[test]
+    fn game_state_long_game_default() {
+        play_long_game(Rules::default(), 100_000);
+    }
+}
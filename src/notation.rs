@@ -0,0 +1,551 @@
+use std::fmt;
+
+use crate::error::GameError;
+use crate::game::Game;
+use crate::models::{Board, Expiry, GameState, Player, Rules, Status};
+
+/// A whole game written down so it can be saved
+/// and shared. It looks like
+///
+/// ```text
+/// [Board "3x3"]
+/// [WinLength "3"]
+/// [Expiry "6"]
+/// [First "O"]
+/// [X "Alice"]
+/// [O "Bob"]
+/// [Date "2024-05-01"]
+/// [Result "X"]
+///
+/// 1. b1 a1 2. c3 a2 3. c2 a3 X
+/// ```
+///
+/// Tiles are a column letter and a row number
+/// counted from the top left, so `a1` is tile 0.
+/// A cubic board is written like `3x3x3`, and
+/// its tiles carry their layer, like `a1:1`.
+/// `Expiry` is a number of marks on the board,
+/// `N per player` or `never`. Games where marks
+/// fall down their column have `[Gravity "yes"]`,
+/// their moves are still the tiles the marks
+/// landed on. `Result` is X, O, 1/2 for a draw
+/// or * for an unfinished game. Games that did
+/// not start on an empty board have a `Start`
+/// header with the comma separated array of the
+/// first position.
+/// The expired marks are not written, they follow
+/// from the rules
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Record {
+    pub rules: Rules,
+    pub start: Option<Vec<u16>>,
+    pub x: Option<String>,
+    pub o: Option<String>,
+    pub date: Option<String>,
+    pub moves: Vec<u16>,
+}
+
+/// Reasons a record can not be read
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum NotationError {
+    /// a header line that is not `[Key "Value"]`
+    BadHeader(String),
+    /// a header with a value that can not be used
+    BadValue { key: String, value: String },
+    /// a move that does not name a tile
+    BadTile(String),
+    /// the move at this ply can not be played
+    IllegalMove { ply: usize, error: GameError },
+    /// the result header does not match the moves
+    ResultMismatch,
+    /// a position string that can not be read
+    BadPosition(String),
+    /// a position string for a position that can
+    /// not happen in a game
+    InvalidPosition(GameError),
+    /// the `Start` header holds a position that
+    /// can not happen with the rules
+    InvalidStart(GameError),
+}
+impl NotationError {
+    /// short stable identifier, passed to
+    /// javascript along with the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            NotationError::BadHeader(_) => "BAD_HEADER",
+            NotationError::BadValue { .. } => "BAD_VALUE",
+            NotationError::BadTile(_) => "BAD_TILE",
+            NotationError::IllegalMove { .. } => "ILLEGAL_MOVE",
+            NotationError::ResultMismatch => "RESULT_MISMATCH",
+            NotationError::BadPosition(_) => "BAD_POSITION",
+            NotationError::InvalidPosition(_) => "INVALID_POSITION",
+            NotationError::InvalidStart(_) => "INVALID_START",
+        }
+    }
+}
+impl fmt::Display for NotationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotationError::BadHeader(line) => write!(f, "can not read header {:?}", line),
+            NotationError::BadValue { key, value } => write!(f, "{:?} is not a valid {}", value, key),
+            NotationError::BadTile(tile) => write!(f, "{:?} is not a tile", tile),
+            NotationError::IllegalMove { ply, error } => write!(f, "move {} can not be played, {}", ply + 1, error),
+            NotationError::ResultMismatch => write!(f, "the result does not match the moves"),
+            NotationError::BadPosition(text) => write!(f, "can not read position {:?}", text),
+            NotationError::InvalidPosition(error) => write!(f, "the position can not happen, {}", error),
+            NotationError::InvalidStart(error) => write!(f, "the Start position can not happen, {}", error),
+        }
+    }
+}
+impl std::error::Error for NotationError {}
+
+impl Record {
+    /// the record of a game, up to its current
+    /// position
+    pub fn from_game(game: &Game) -> Self {
+        let first = game.positions().next().unwrap_or(game.state());
+        let start = if first.array.iter().all(|&n| n == 0) {
+            None
+        } else {
+            Some(first.array.clone())
+        };
+
+        Self {
+            rules: first.rules,
+            start,
+            moves: game.moves().iter().take(game.ply()).map(|played| played.tile).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// plays the moves of the record from its
+    /// starting position
+    pub fn to_game(&self) -> Result<Game, NotationError> {
+        let mut game = match &self.start {
+            Some(array) => Game::load(self.rules, array)
+            .map_err(NotationError::InvalidStart)?,
+            None => Game::from_state(GameState::empty(self.rules)),
+        };
+        for (ply, &tile) in self.moves.iter().enumerate() {
+            game.turn(tile).map_err(|error| NotationError::IllegalMove { ply, error })?;
+        }
+        Ok(game)
+    }
+
+    /// reads a record, the moves are checked by
+    /// playing them
+    pub fn parse(text: &str) -> Result<Self, NotationError> {
+        let mut record = Record::default();
+        let (mut width, mut height, mut depth, mut win_length) = (3, 3, 1, None);
+        let mut result = None;
+        let mut movetext = Vec::new();
+
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            if !line.starts_with('[') {
+                movetext.push(line);
+                continue;
+            }
+
+            let (key, value) = parse_header(line)?;
+            let bad_value = || NotationError::BadValue { key: key.to_string(), value: value.to_string() };
+            match key {
+                "Board" => {
+                    // a third size is the number of layers
+                    let sizes: Vec<u16> = value
+                    .split('x')
+                    .map(|size| size.parse().map_err(|_| bad_value()))
+                    .collect::<Result<_, _>>()?;
+                    match sizes[..] {
+                        [w, h] => (width, height, depth) = (w, h, 1),
+                        [w, h, d] => (width, height, depth) = (w, h, d),
+                        _ => return Err(bad_value()),
+                    }
+                    // columns are named by a single letter
+                    if width == 0 || width > 26 || height == 0 || depth == 0 {
+                        return Err(bad_value());
+                    }
+                },
+                "WinLength" => win_length = Some(value.parse().map_err(|_| bad_value())?),
+                "Expiry" => record.rules.expiry = parse_expiry(value).ok_or_else(bad_value)?,
+                "First" => record.rules.first = parse_player(value).ok_or_else(bad_value)?,
+                "Gravity" => record.rules.gravity = parse_yes(value).ok_or_else(bad_value)?,
+                "X" => record.x = Some(value.to_string()),
+                "O" => record.o = Some(value.to_string()),
+                "Date" => record.date = Some(value.to_string()),
+                "Result" => result = Some(parse_result(value).ok_or_else(bad_value)?),
+                "Start" => {
+                    let array: Result<Vec<u16>, _> = value.split(',').map(|n| n.trim().parse()).collect();
+                    record.start = Some(array.map_err(|_| bad_value())?);
+                },
+                _ => {},
+            }
+        }
+        let shortest = if depth > 1 { width.min(height).min(depth) } else { width.min(height) };
+        let win_length = win_length.unwrap_or(shortest);
+        if win_length == 0 || win_length > width.max(height).max(depth) {
+            return Err(NotationError::BadValue { key: "WinLength".to_string(), value: win_length.to_string() });
+        }
+        record.rules.board = Board::layered(width, height, depth, win_length);
+        // the start is checked once all the rules
+        // are known, before any move
+        if let Some(start) = &record.start {
+            GameState::try_with_rules(record.rules, start).map_err(NotationError::InvalidStart)?;
+        }
+
+        for token in movetext.iter().flat_map(|line| line.split_whitespace()) {
+            if token.ends_with('.') || parse_result(token).is_some() {
+                continue;
+            }
+            let tile = parse_tile(&record.rules.board, token)
+            .ok_or_else(|| NotationError::BadTile(token.to_string()))?;
+            record.moves.push(tile);
+        }
+
+        let game = record.to_game()?;
+        if result.map_or(false, |result| result != game.state().status()) {
+            return Err(NotationError::ResultMismatch);
+        }
+        Ok(record)
+    }
+}
+
+/// writes the record, the result is found by
+/// playing the moves
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let board = self.rules.board;
+        let status = self.to_game().map_or(Status::InProgress, |game| game.state().status());
+        let result = match status {
+            Status::Won(Player::X) => "X",
+            Status::Won(Player::O) => "O",
+            Status::Drawn => "1/2",
+            Status::InProgress => "*",
+        };
+
+        if board.is_cubic() {
+            writeln!(f, "[Board \"{}x{}x{}\"]", board.width, board.height, board.depth)?;
+        } else {
+            writeln!(f, "[Board \"{}x{}\"]", board.width, board.height)?;
+        }
+        writeln!(f, "[WinLength \"{}\"]", board.win_length)?;
+        match self.rules.expiry {
+            Expiry::Global(window) => writeln!(f, "[Expiry \"{}\"]", window)?,
+            Expiry::PerPlayer(window) => writeln!(f, "[Expiry \"{} per player\"]", window)?,
+            Expiry::Never => writeln!(f, "[Expiry \"never\"]")?,
+        }
+        writeln!(f, "[First \"{:?}\"]", self.rules.first)?;
+        if self.rules.gravity {
+            writeln!(f, "[Gravity \"yes\"]")?;
+        }
+        if let Some(x) = &self.x {
+            writeln!(f, "[X \"{}\"]", x)?;
+        }
+        if let Some(o) = &self.o {
+            writeln!(f, "[O \"{}\"]", o)?;
+        }
+        if let Some(date) = &self.date {
+            writeln!(f, "[Date \"{}\"]", date)?;
+        }
+        writeln!(f, "[Result \"{}\"]", result)?;
+        if let Some(start) = &self.start {
+            let array: Vec<String> = start.iter().map(|n| n.to_string()).collect();
+            writeln!(f, "[Start \"{}\"]", array.join(","))?;
+        }
+        writeln!(f)?;
+
+        // a game from a position keeps counting
+        // from the move numbers of its array
+        let offset = self.start.as_ref().map_or(0, |array| {
+            usize::from(array.iter().copied().max().unwrap_or(0))
+        });
+        let mut tokens = Vec::new();
+        for (i, &tile) in self.moves.iter().enumerate() {
+            let ply = i + offset;
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            } else if i == 0 {
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+            tokens.push(tile_name(&board, tile));
+        }
+        tokens.push(result.to_string());
+        writeln!(f, "{}", tokens.join(" "))
+    }
+}
+
+/// the name of a tile, a column letter and a
+/// row number from the top left. On cubic boards
+/// the layer follows after a colon, counted from
+/// the top, like `b2:3`
+pub fn tile_name(board: &Board, tile: u16) -> String {
+    let (column, row, layer) = board.coordinates(tile);
+    let column = (b'a' + column as u8) as char;
+    if board.is_cubic() {
+        format!("{}{}:{}", column, row + 1, layer + 1)
+    } else {
+        format!("{}{}", column, row + 1)
+    }
+}
+
+/// inverse of `tile_name`, None if the name is
+/// not a tile of the board
+pub fn parse_tile(board: &Board, name: &str) -> Option<u16> {
+    let mut chars = name.chars();
+    let column = chars.next()?;
+    if !column.is_ascii_lowercase() {
+        return None;
+    }
+    let column = column as u16 - 'a' as u16;
+    let (row, layer) = match chars.as_str().split_once(':') {
+        Some((row, layer)) if board.is_cubic() => (row, layer.parse().ok()?),
+        None if !board.is_cubic() => (chars.as_str(), 1),
+        _ => return None,
+    };
+    let row: u16 = row.parse().ok()?;
+    if row == 0 || layer == 0 {
+        return None;
+    }
+    board.tile(column, row - 1, layer - 1)
+}
+
+/// the name of a column marks are dropped in
+/// with gravity, its letter. On cubic boards the
+/// layer follows like in `tile_name`, `b:3`
+pub fn column_name(board: &Board, column: u16) -> String {
+    let letter = (b'a' + (column % board.width) as u8) as char;
+    if board.is_cubic() {
+        format!("{}:{}", letter, column / board.width + 1)
+    } else {
+        letter.to_string()
+    }
+}
+
+/// inverse of `column_name`, None if the name
+/// is not a column of the board
+pub fn parse_column(board: &Board, name: &str) -> Option<u16> {
+    let (letter, layer) = match name.split_once(':') {
+        Some((letter, layer)) if board.is_cubic() => (letter, layer.parse::<u16>().ok()?),
+        None if !board.is_cubic() => (name, 1),
+        _ => return None,
+    };
+    let mut chars = letter.chars();
+    let column = chars.next().filter(|c| c.is_ascii_lowercase() && chars.next().is_none())? as u16 - 'a' as u16;
+    if column >= board.width || layer == 0 || layer > board.depth {
+        return None;
+    }
+    Some((layer - 1) * board.width + column)
+}
+
+/// private function. Splits `[Key "Value"]`
+fn parse_header(line: &str) -> Result<(&str, &str), NotationError> {
+    let bad_header = || NotationError::BadHeader(line.to_string());
+    let inner = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')).ok_or_else(bad_header)?;
+    let (key, value) = inner.split_once(' ').ok_or_else(bad_header)?;
+    let value = value.trim().strip_prefix('"').and_then(|v| v.strip_suffix('"')).ok_or_else(bad_header)?;
+    Ok((key, value))
+}
+
+fn parse_expiry(value: &str) -> Option<Expiry> {
+    if value == "never" {
+        return Some(Expiry::Never);
+    }
+    match value.strip_suffix("per player") {
+        Some(window) => Some(Expiry::PerPlayer(window.trim().parse().ok()?)),
+        None => Some(Expiry::Global(value.parse().ok()?)),
+    }
+}
+
+fn parse_player(value: &str) -> Option<Player> {
+    match value {
+        "X" => Some(Player::X),
+        "O" => Some(Player::O),
+        _ => None,
+    }
+}
+
+fn parse_yes(value: &str) -> Option<bool> {
+    match value {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_result(value: &str) -> Option<Status> {
+    match value {
+        "X" => Some(Status::Won(Player::X)),
+        "O" => Some(Status::Won(Player::O)),
+        "1/2" => Some(Status::Drawn),
+        "*" => Some(Status::InProgress),
+        _ => None,
+    }
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::{column_name, parse_column, parse_tile, tile_name, NotationError, Record};
+    use crate::error::GameError;
+    use crate::game::Game;
+    use crate::models::{Board, Expiry, Player, Rules};
+
+    const GAME: &str = "[Board \"3x3\"]
+[WinLength \"3\"]
+[Expiry \"6\"]
+[First \"O\"]
+[X \"Alice\"]
+[O \"Bob\"]
+[Date \"2024-05-01\"]
+[Result \"X\"]
+
+1. b1 a1 2. c3 a2 3. c2 a3 X
+";
+
+    #[test]
+    fn notation_tiles() {
+        let board = Board::default();
+        assert_eq!(tile_name(&board, 0), "a1");
+        assert_eq!(tile_name(&board, 5), "c2");
+        assert_eq!(parse_tile(&board, "c2"), Some(5));
+        assert_eq!(parse_tile(&board, "d1"), None);
+        assert_eq!(parse_tile(&board, "a4"), None);
+    }
+
+    #[test]
+    fn notation_cube() {
+        let board = Board::cube(3, 3);
+        assert_eq!(tile_name(&board, 13), "b2:2");
+        assert_eq!(parse_tile(&board, "c3:3"), Some(26));
+        assert_eq!(parse_tile(&board, "c3"), None);
+        assert_eq!(parse_tile(&board, "c3:4"), None);
+        assert_eq!(parse_tile(&Board::default(), "c3:1"), None);
+
+        let rules = Rules::cube(3, None, None, None).without_expiry();
+        let mut game = Game::load(rules, &[0; 27]).unwrap();
+        for tile in [0, 1, 13, 2, 26] {
+            game.turn(tile).unwrap();
+        }
+        let text = Record::from_game(&game).to_string();
+        assert!(text.contains("[Board \"3x3x3\"]"));
+        assert!(text.contains("1. a1:1 b1:1 2. b2:2 c1:1 3. c3:3 O"));
+        let record = Record::parse(&text).unwrap();
+        assert_eq!(record.rules, rules);
+        assert_eq!(record.moves, vec![0, 1, 13, 2, 26]);
+    }
+
+    #[test]
+    fn notation_gravity() {
+        let board = Board::square(4, 3);
+        assert_eq!(column_name(&board, 2), "c");
+        assert_eq!(parse_column(&board, "c"), Some(2));
+        assert_eq!(parse_column(&board, "e"), None);
+        assert_eq!(parse_column(&board, "c1"), None);
+        let cube = Board::cube(3, 3);
+        assert_eq!(column_name(&cube, 4), "b:2");
+        assert_eq!(parse_column(&cube, "b:2"), Some(4));
+        assert_eq!(parse_column(&cube, "b"), None);
+
+        let rules = Rules::new(board, Expiry::Global(4)).with_gravity();
+        let mut game = Game::load(rules, &[0; 16]).unwrap();
+        for column in [0, 0, 1, 3, 2] {
+            game.drop_mark(column).unwrap();
+        }
+        let text = Record::from_game(&game).to_string();
+        assert!(text.contains("[Gravity \"yes\"]"));
+        assert!(text.contains("1. a4 a3 2. b4 d4 3. c4 *"));
+        let record = Record::parse(&text).unwrap();
+        assert_eq!(record.rules, rules);
+        assert_eq!(record.to_game().unwrap().array(), game.array());
+    }
+
+    #[test]
+    /// reading a record and writing it again
+    /// gives the same text
+    fn notation_round_trip() {
+        let record = Record::parse(GAME).unwrap();
+        assert_eq!(record.moves, vec![1, 0, 8, 3, 5, 6]);
+        assert_eq!(record.x.as_deref(), Some("Alice"));
+        assert_eq!(record.to_game().unwrap().winner(), 1);
+        assert_eq!(record.to_string(), GAME);
+    }
+
+    #[test]
+    /// a full classic board is written as a draw
+    fn notation_draw() {
+        let mut game = Game::load(Rules::classic(), &[0; 9]).unwrap();
+        for tile in [4, 0, 2, 6, 3, 5, 7, 1, 8] {
+            game.turn(tile).unwrap();
+        }
+        let text = Record::from_game(&game).to_string();
+        assert!(text.contains("[Expiry \"never\"]"));
+        assert!(text.contains("[Result \"1/2\"]"));
+        let record = Record::parse(&text).unwrap();
+        assert_eq!(record.rules, Rules::classic());
+        assert_eq!(record.to_game().unwrap().status(), 2);
+        assert_eq!(Record::parse(&text.replace("1/2", "X")), Err(NotationError::ResultMismatch));
+    }
+
+    #[test]
+    fn notation_from_game() {
+        let rules = Rules::new(Board::square(4, 3), Expiry::PerPlayer(3)).with_first(Player::X);
+        let mut game = Game::load(rules, &[0; 16]).unwrap();
+        for tile in [5, 0, 10, 15] {
+            game.turn(tile).unwrap();
+        }
+        game.undo();
+
+        let text = Record::from_game(&game).to_string();
+        assert!(text.contains("[Board \"4x4\"]"));
+        assert!(text.contains("[Expiry \"3 per player\"]"));
+        assert!(text.contains("[First \"X\"]"));
+        assert!(text.ends_with("1. b2 a1 2. c3 *\n"));
+        assert_eq!(Record::parse(&text).unwrap().to_game().unwrap().array(), game.array());
+    }
+
+    #[test]
+    /// a game loaded from an array keeps its
+    /// starting position
+    fn notation_start_position() {
+        let array = [
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 0,
+        ];
+        let mut game = Game::load(Rules::default(), &array).unwrap();
+        game.turn(8).unwrap();
+
+        let text = Record::from_game(&game).to_string();
+        assert!(text.contains("[Start \"0,15,0,13,11,14,12,16,0\"]"));
+        assert!(text.ends_with("9. c3 *\n"));
+        assert_eq!(Record::parse(&text).unwrap().to_game().unwrap().array(), game.array());
+    }
+
+    #[test]
+    fn notation_errors() {
+        assert_eq!(
+            Record::parse("[Board 3x3]"),
+            Err(NotationError::BadHeader("[Board 3x3]".to_string())),
+        );
+        assert_eq!(
+            Record::parse("[Expiry \"many\"]"),
+            Err(NotationError::BadValue { key: "Expiry".to_string(), value: "many".to_string() }),
+        );
+        assert_eq!(Record::parse("1. z9"), Err(NotationError::BadTile("z9".to_string())));
+        assert_eq!(
+            Record::parse("1. b2 b2"),
+            Err(NotationError::IllegalMove { ply: 1, error: GameError::OccupiedCell(4) }),
+        );
+        assert_eq!(Record::parse(&GAME.replace("[Result \"X\"]", "[Result \"O\"]")), Err(NotationError::ResultMismatch));
+        assert_eq!(
+            Record::parse("[Start \"1,1,0,0,0,0,0,0,0\"]\n1. z9"),
+            Err(NotationError::InvalidStart(GameError::DuplicateMove(1))),
+        );
+    }
+}
@@ -0,0 +1,664 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::error::GameError;
+use crate::models::{Board, Cell, Expiry, GameState, Player, Rules, RENUMBER_AT};
+use crate::symmetry::Symmetry;
+
+/// The most tiles a bitboard can hold
+pub const MAX_TILES: usize = 64;
+
+/// The win lines of the classic 3x3 board
+const LINES_3X3: [u64; 8] = [
+    0b000_000_111,
+    0b000_111_000,
+    0b111_000_000,
+    0b001_001_001,
+    0b010_010_010,
+    0b100_100_100,
+    0b100_010_001,
+    0b001_010_100,
+];
+
+/// Random keys of the Zobrist hash, one for
+/// every player, tile and place in the order
+/// the marks expire in
+static KEYS: [u64; 2 * MAX_TILES * MAX_TILES] = zobrist_keys();
+
+/// The key added to the hash when X is to move
+const X_TO_MOVE: u64 = 0x2D35_8DCC_AA6C_78A5;
+
+/// A position as bitboards, one bit per tile,
+/// for the solver and self-play where
+/// `GameState` is too slow. Copying a position
+/// is cheap and nothing it does allocates.
+/// The marks are kept oldest first in `queue`
+/// instead of as move numbers, only `last` is
+/// kept to know who moves and when marks start
+/// to expire. The Zobrist hash is updated with
+/// every move, see `zobrist`
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Position {
+    rules: Rules,
+    lines: &'static [u64],
+    x: u64,
+    o: u64,
+    expiring: u64,
+    queue: [u8; MAX_TILES],
+    len: u8,
+    last: u16,
+    zobrist: u64,
+}
+impl Position {
+    /// an empty board with the given rules
+    pub fn empty(rules: Rules) -> Result<Self, GameError> {
+        Position::from_state(&GameState::empty(rules))
+    }
+
+    /// the position of a game state. The state
+    /// must pass `GameState::validate`, so the
+    /// conversion back with `to_state` gives the
+    /// same state
+    pub fn from_state(game_state: &GameState) -> Result<Self, GameError> {
+        let rules = game_state.rules;
+        if rules.board.len() > MAX_TILES {
+            return Err(GameError::BoardTooLarge(rules.board.len()));
+        }
+        game_state.validate()?;
+
+        let mut marks: Vec<(u16, u8)> = game_state.array
+        .iter()
+        .enumerate()
+        .filter(|&(_, &n)| n != 0)
+        .map(|(tile, &n)| (n, tile as u8))
+        .collect();
+        marks.sort_unstable();
+
+        let mut position = Self {
+            rules,
+            lines: win_masks(&rules.board),
+            x: 0,
+            o: 0,
+            expiring: 0,
+            queue: [0; MAX_TILES],
+            len: marks.len() as u8,
+            last: game_state.last,
+            zobrist: 0,
+        };
+        for (i, &(n, tile)) in marks.iter().enumerate() {
+            position.queue[i] = tile;
+            match rules.owner(n) {
+                Cell::X => position.x |= 1 << tile,
+                _ => position.o |= 1 << tile,
+            }
+        }
+        position.mark_expiring();
+        position.zobrist = position.compute_zobrist();
+        Ok(position)
+    }
+
+    /// the game state of the position, the marks
+    /// are numbered backwards from the last move
+    pub fn to_state(&self) -> GameState {
+        let mut array = vec![0; self.rules.board.len()];
+        let mut n = self.last + 1;
+        for &tile in self.queue[..self.len as usize].iter().rev() {
+            // the next lower number made by the
+            // owner of the mark
+            n -= 1;
+            if self.rules.owner(n) != self.owner(tile as u16) {
+                n -= 1;
+            }
+            array[tile as usize] = n;
+        }
+        GameState::with_rules(self.rules, array)
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// the tiles of X's marks, expiring or not
+    pub fn x(&self) -> u64 {
+        self.x
+    }
+
+    /// the tiles of O's marks, expiring or not
+    pub fn o(&self) -> u64 {
+        self.o
+    }
+
+    /// the win lines of the board as bitboards
+    pub fn lines(&self) -> &'static [u64] {
+        self.lines
+    }
+
+    /// the tile of the mark that is removed by
+    /// the next move, if any
+    pub fn expiring_tile(&self) -> Option<u16> {
+        (self.expiring != 0).then(|| self.expiring.trailing_zeros() as u16)
+    }
+
+    /// the player who makes the next move
+    pub fn to_move(&self) -> Player {
+        self.rules.player(self.last + 1)
+    }
+
+    /// the mark on a tile, Expiring for the mark
+    /// that is removed by the next move
+    pub fn get(&self, tile: u16) -> Cell {
+        let bit = 1 << tile;
+        if self.expiring & bit != 0 {
+            Cell::E
+        } else if self.x & bit != 0 {
+            Cell::X
+        } else if self.o & bit != 0 {
+            Cell::O
+        } else {
+            Cell::N
+        }
+    }
+
+    /// private function. Whose mark is on a
+    /// tile that is not empty
+    fn owner(&self, tile: u16) -> Cell {
+        if self.x & 1 << tile != 0 { Cell::X } else { Cell::O }
+    }
+
+    /// the player with a complete line, or None.
+    /// Expiring marks do not count. If both have
+    /// one the player who moved wins, see
+    /// `GameState::win`
+    pub fn winner(&self) -> Cell {
+        let x = self.x & !self.expiring;
+        let o = self.o & !self.expiring;
+        let (mut x_won, mut o_won) = (false, false);
+        for &line in self.lines {
+            x_won |= x & line == line;
+            o_won |= o & line == line;
+        }
+        match (x_won, o_won) {
+            (true, true) => self.rules.owner(self.last),
+            (true, _) => Cell::X,
+            (_, true) => Cell::O,
+            _ => Cell::N,
+        }
+    }
+
+    /// the tiles the player to move can play on,
+    /// none once the game has been won. With
+    /// gravity only the empty tiles on the bottom
+    /// row or right above a mark
+    pub fn legal_moves(&self) -> Moves {
+        if self.winner() != Cell::N {
+            return Moves(0);
+        }
+        let tiles = self.rules.board.len();
+        let board = if tiles == MAX_TILES { u64::MAX } else { (1 << tiles) - 1 };
+        let free = board & !(self.x | self.o);
+        if !self.rules.gravity {
+            return Moves(free);
+        }
+        let bottom = bottom_row(&self.rules.board);
+        let above_marks = ((self.x | self.o) >> self.rules.board.width) & !bottom;
+        Moves(free & (bottom | above_marks))
+    }
+
+    /// the same checks as `GameState::turn`
+    /// before playing the tile
+    pub fn try_play(&self, tile: u16) -> Result<Self, GameError> {
+        if tile as usize >= self.rules.board.len() {
+            return Err(GameError::OutOfBounds(tile));
+        }
+        match self.get(tile) {
+            Cell::N => {},
+            Cell::E => return Err(GameError::ExpiringCell(tile)),
+            _ => return Err(GameError::OccupiedCell(tile)),
+        }
+        let winner = self.winner();
+        if winner != Cell::N {
+            return Err(GameError::GameWon(winner));
+        }
+        if self.rules.gravity && self.legal_moves().0 & 1 << tile == 0 {
+            return Err(GameError::FloatingTile(tile));
+        }
+        Ok(self.play(tile))
+    }
+
+    /// plays a tile from `legal_moves` without
+    /// checking it. The expiring mark is removed
+    pub fn play(&self, tile: u16) -> Self {
+        debug_assert!(self.get(tile) == Cell::N, "tile {} is not free", tile);
+        let mut new = *self;
+        if new.last >= RENUMBER_AT {
+            new.renumber();
+        }
+
+        let expired = new.expiring_tile();
+        if new.expiring != 0 {
+            let expired = new.expiring.trailing_zeros() as u8;
+            let len = new.len as usize;
+            if let Some(i) = new.queue[..len].iter().position(|&t| t == expired) {
+                // every younger mark moves one place
+                // closer to expiring
+                new.zobrist ^= new.key(i);
+                for j in i + 1..len {
+                    new.zobrist ^= new.key(j) ^ zobrist_key(new.owner(new.queue[j] as u16), new.queue[j], j - 1);
+                }
+                new.queue.copy_within(i + 1..len, i);
+                // unused entries stay zero so equal
+                // positions compare equal
+                new.queue[len - 1] = 0;
+                new.len -= 1;
+            }
+            new.x &= !new.expiring;
+            new.o &= !new.expiring;
+        }
+
+        new.last += 1;
+        match new.rules.player(new.last) {
+            Player::X => new.x |= 1 << tile,
+            Player::O => new.o |= 1 << tile,
+        }
+        new.queue[new.len as usize] = tile as u8;
+        new.zobrist ^= new.key(new.len as usize) ^ X_TO_MOVE;
+        new.len += 1;
+        if let Some(expired) = expired.filter(|_| new.rules.gravity) {
+            new.settle(expired);
+        }
+        new.mark_expiring();
+        new
+    }
+
+    /// private function. Lets every mark above
+    /// an emptied tile fall one tile down, the
+    /// same as `GameState::turn` with gravity
+    fn settle(&mut self, emptied: u16) {
+        let board = self.rules.board;
+        let (column, mut row, layer) = board.coordinates(emptied);
+        while let Some(above) = row.checked_sub(1).and_then(|up| board.tile(column, up, layer)) {
+            let bit = 1 << above;
+            if (self.x | self.o) & bit == 0 {
+                break;
+            }
+            let below = above + board.width;
+            let i = self.queue[..self.len as usize].iter().position(|&t| u16::from(t) == above).unwrap();
+            self.zobrist ^= self.key(i);
+            if self.x & bit != 0 {
+                self.x ^= bit | 1 << below;
+            } else {
+                self.o ^= bit | 1 << below;
+            }
+            self.queue[i] = below as u8;
+            self.zobrist ^= self.key(i);
+            row -= 1;
+        }
+    }
+
+    /// the Zobrist hash of the position. Marks are
+    /// hashed with their place in the order they
+    /// expire in, so the same marks played in
+    /// another order hash differently. Move numbers
+    /// and rules are not part of the hash
+    pub fn zobrist(&self) -> u64 {
+        self.zobrist
+    }
+
+    /// the position moved by a symmetry of its
+    /// rules, see `Symmetry::of_rules`
+    pub fn transform(&self, symmetry: Symmetry) -> Self {
+        let board = &self.rules.board;
+        debug_assert!(Symmetry::of_rules(&self.rules).any(|s| s == symmetry), "{:?} does not fit the rules", symmetry);
+        let mut new = *self;
+        new.x = symmetry.apply_mask(board, self.x);
+        new.o = symmetry.apply_mask(board, self.o);
+        new.expiring = symmetry.apply_mask(board, self.expiring);
+        for tile in new.queue[..self.len as usize].iter_mut() {
+            *tile = symmetry.apply(board, u16::from(*tile)) as u8;
+        }
+        new.zobrist = new.compute_zobrist();
+        new
+    }
+
+    /// the same position for every symmetric one:
+    /// the smallest of its transforms, together
+    /// with the symmetry that gives it. Transposition
+    /// tables can share results between symmetric
+    /// positions by storing the canonical one
+    pub fn canonical(&self) -> (Self, Symmetry) {
+        Symmetry::of_rules(&self.rules)
+        .map(|symmetry| (self.transform(symmetry), symmetry))
+        .min_by_key(|(position, _)| (position.queue, position.x))
+        .unwrap_or((*self, Symmetry::Identity))
+    }
+
+    /// private function. The key of the mark at
+    /// place `i` of the queue
+    fn key(&self, i: usize) -> u64 {
+        let tile = self.queue[i];
+        zobrist_key(self.owner(u16::from(tile)), tile, i)
+    }
+
+    /// private function. The Zobrist hash made
+    /// from scratch
+    fn compute_zobrist(&self) -> u64 {
+        let side = if self.to_move() == Player::X { X_TO_MOVE } else { 0 };
+        (0..self.len as usize).fold(side, |zobrist, i| zobrist ^ self.key(i))
+    }
+
+    /// private function. The same as
+    /// `GameState::renumber`, the oldest mark
+    /// becomes move 1 or 2
+    fn renumber(&mut self) {
+        let len = u16::from(self.len);
+        if len == 0 {
+            self.last = 0;
+            return;
+        }
+        let oldest = self.last + 1 - len;
+        self.last = 2 - oldest % 2 + len - 1;
+    }
+
+    /// private function. Finds the mark removed
+    /// by the next move, see `Rules::expiring_move`
+    fn mark_expiring(&mut self) {
+        let next = self.last + 1;
+        let (moves, own) = match self.rules.expiry {
+            Expiry::Global(_) | Expiry::Never => (self.last, self.x | self.o),
+            Expiry::PerPlayer(_) => (
+                (self.last + next % 2) / 2,
+                match self.rules.player(next) {
+                    Player::X => self.x,
+                    Player::O => self.o,
+                },
+            ),
+        };
+        self.expiring = 0;
+        if moves >= self.rules.expiry.window() {
+            if let Some(&tile) = self.queue[..self.len as usize].iter().find(|&&t| own & 1 << t != 0) {
+                self.expiring = 1 << tile;
+            }
+        }
+    }
+}
+
+/// positions hash by their Zobrist hash
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.zobrist);
+    }
+}
+
+/// The tiles of a bitboard, lowest first
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Moves(pub u64);
+impl Iterator for Moves {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        if self.0 == 0 {
+            return None;
+        }
+        let tile = self.0.trailing_zeros() as u16;
+        self.0 &= self.0 - 1;
+        Some(tile)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+impl ExactSizeIterator for Moves {}
+
+/// the key of a mark of `owner` on `tile` at
+/// place `i` of the expiry order
+fn zobrist_key(owner: Cell, tile: u8, i: usize) -> u64 {
+    let player = usize::from(owner == Cell::O);
+    KEYS[(player * MAX_TILES + i) * MAX_TILES + tile as usize]
+}
+
+/// the Zobrist keys, made at compile time by
+/// splitmix64 so they are the same every run
+const fn zobrist_keys() -> [u64; 2 * MAX_TILES * MAX_TILES] {
+    let mut keys = [0; 2 * MAX_TILES * MAX_TILES];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < keys.len() {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        keys[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    keys
+}
+
+thread_local! {
+    static MASKS: RefCell<HashMap<Board, &'static [u64]>> = RefCell::new(HashMap::new());
+}
+
+/// private function. The tiles of the bottom
+/// row of every layer, where marks land in an
+/// empty column with gravity
+fn bottom_row(board: &Board) -> u64 {
+    (0..board.depth)
+    .flat_map(|layer| (0..board.width).map(move |column| (column, layer)))
+    .filter_map(|(column, layer)| board.tile(column, board.height - 1, layer))
+    .fold(0, |mask, tile| mask | 1 << tile)
+}
+
+/// one bitboard per win line of the board. The
+/// masks of a board are made once and kept for
+/// the rest of the program
+pub fn win_masks(board: &Board) -> &'static [u64] {
+    if *board == Board::default() {
+        return &LINES_3X3;
+    }
+    MASKS.with(|masks| {
+        *masks.borrow_mut().entry(*board).or_insert_with(|| {
+            let lines: Vec<u64> = board
+            .lines()
+            .iter()
+            .map(|line| line.iter().fold(0, |mask, &tile| mask | 1 << tile))
+            .collect();
+            Box::leak(lines.into_boxed_slice())
+        })
+    })
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::{win_masks, Position};
+    use crate::error::GameError;
+    use crate::models::{Board, Cell, Expiry, GameState, Player, Rules};
+    use crate::symmetry::Symmetry;
+
+    /// plays the same moves on a game state and a
+    /// position and checks they stay the same
+    fn play_both(rules: Rules, moves: usize) {
+        let mut game_state = GameState::empty(rules);
+        let mut position = Position::from_state(&game_state).unwrap();
+        for i in 0..moves {
+            assert_eq!(position.to_state(), game_state, "after {} moves", i);
+            assert_eq!(Position::from_state(&game_state), Ok(position));
+            assert_eq!(position.winner(), game_state.winner());
+            assert_eq!(position.legal_moves().collect::<Vec<u16>>(), game_state.legal_moves());
+
+            let legal = game_state.legal_moves();
+            if legal.is_empty() {
+                game_state = GameState::empty(rules);
+                position = Position::empty(rules).unwrap();
+                continue;
+            }
+            let tile = legal[(i * 7 + i / 3) % legal.len()];
+            game_state = game_state.turn(tile).unwrap();
+            position = position.play(tile);
+        }
+    }
+
+    #[test]
+    fn position_win_masks() {
+        let mut classic: Vec<u64> = win_masks(&Board::default()).to_vec();
+        let mut lines: Vec<u64> = Board::default()
+        .lines()
+        .iter()
+        .map(|line| line.iter().fold(0, |mask, &tile| mask | 1 << tile))
+        .collect();
+        classic.sort_unstable();
+        lines.sort_unstable();
+        assert_eq!(classic, lines);
+        assert_eq!(win_masks(&Board::square(8, 5)).len(), 4 * 8 + 4 * 8 + 2 * 4 * 4);
+    }
+
+    #[test]
+    fn position_matches_game_state() {
+        play_both(Rules::default(), 500);
+        play_both(Rules::new(Board::square(4, 3), Expiry::PerPlayer(3)).with_first(Player::X), 500);
+        play_both(Rules::new(Board::new(5, 3, 3), Expiry::Global(5)), 500);
+        play_both(Rules::new(Board::square(8, 5), Expiry::Global(12)), 500);
+    }
+
+    #[test]
+    /// marks that fall after an expiring mark is
+    /// removed end up in the same place
+    fn position_gravity() {
+        play_both(Rules::new(Board::new(5, 4, 3), Expiry::Global(6)).with_gravity(), 500);
+        play_both(Rules::new(Board::square(4, 3), Expiry::PerPlayer(3)).with_gravity(), 500);
+        play_both(Rules::new(Board::new(7, 6, 4), Expiry::Never).with_gravity(), 500);
+        play_both(Rules::new(Board::cube(3, 3), Expiry::Global(8)).with_gravity(), 500);
+
+        let rules = Rules::new(Board::square(4, 3), Expiry::Global(6)).with_gravity();
+        let position = Position::empty(rules).unwrap();
+        assert_eq!(position.legal_moves().collect::<Vec<u16>>(), vec![12, 13, 14, 15]);
+        assert_eq!(position.try_play(8), Err(GameError::FloatingTile(8)));
+    }
+
+    #[test]
+    /// positions renumber like game states do
+    fn position_long_game() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::PerPlayer(3));
+        let mut game_state = GameState::empty(rules);
+        let mut position = Position::empty(rules).unwrap();
+        for i in 0..70_000_u32 {
+            let tile = position.legal_moves().nth(i as usize % 3).unwrap();
+            game_state = game_state.turn(tile).unwrap();
+            position = position.play(tile);
+        }
+        assert_eq!(position.to_state(), game_state);
+        assert!(game_state.last < 5_000);
+    }
+
+    #[test]
+    fn position_expiring() {
+        let game_state = GameState::from_array([
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 0,
+        ]);
+        let position = Position::from_state(&game_state).unwrap();
+        assert_eq!(position.expiring_tile(), Some(4));
+        assert_eq!(position.get(4), Cell::E);
+        assert_eq!(position.x(), 1 << 5 | 1 << 6 | 1 << 7);
+        assert_eq!(position.to_move(), Player::O);
+
+        let next = position.play(8);
+        assert_eq!(next.get(4), Cell::N);
+        assert_eq!(next.expiring_tile(), Some(6));
+        assert_eq!(next.to_state(), game_state.turn(8).unwrap());
+    }
+
+    #[test]
+    fn position_errors() {
+        let rules = Rules::new(Board::square(9, 3), Expiry::Global(6));
+        assert_eq!(Position::empty(rules), Err(GameError::BoardTooLarge(81)));
+
+        let game_state = GameState::from_array([
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 0,
+        ]);
+        let position = Position::from_state(&game_state).unwrap();
+        for tile in [1, 4, 9] {
+            assert_eq!(position.try_play(tile).err(), game_state.turn(tile).err());
+        }
+
+        let mut invalid = game_state;
+        invalid.array[4] = 10;
+        assert_eq!(Position::from_state(&invalid), Err(GameError::StaleMove(10)));
+    }
+
+    #[test]
+    /// the same marks played in another order
+    /// expire differently, so they hash differently
+    fn position_zobrist_order() {
+        let position = |text: &str| {
+            Position::from_state(&GameState::from_notation(Rules::default(), text).unwrap()).unwrap()
+        };
+        let reading = position("XX./OO./... o 5");
+        let other = position("XX./OO./... o 5 b2,a1,a2,b1");
+        assert_eq!(reading.x(), other.x());
+        assert_ne!(reading.zobrist(), other.zobrist());
+        assert_ne!(reading.canonical().0, other.canonical().0);
+
+        // the same position reached by other moves
+        // hashes the same
+        let empty = Position::empty(Rules::default()).unwrap();
+        let one = [4, 0, 8, 2, 1, 7, 5].iter().fold(empty, |position, &tile| position.play(tile));
+        let two = [3, 6, 4, 0, 8, 2, 1, 7, 5].iter().fold(empty, |position, &tile| position.play(tile));
+        assert_eq!(one.to_state().state, two.to_state().state);
+        assert_ne!(one, two);
+        assert_eq!(one.zobrist(), two.zobrist());
+        assert_eq!(one.canonical().0.zobrist(), two.canonical().0.zobrist());
+        assert_ne!(empty.zobrist(), empty.play(4).zobrist());
+    }
+
+    #[test]
+    /// every transform of a position has the same
+    /// canonical position, and transforms undo
+    fn position_canonical() {
+        let gravity = Rules::new(Board::square(4, 3), Expiry::Global(6)).with_gravity();
+        for rules in [Rules::default(), Rules::new(Board::new(5, 3, 3), Expiry::PerPlayer(2)), gravity] {
+            let mut position = Position::empty(rules).unwrap();
+            for i in 0..40 {
+                let (canonical, symmetry) = position.canonical();
+                assert_eq!(position.transform(symmetry), canonical);
+                for s in Symmetry::of_rules(&rules) {
+                    let moved = position.transform(s);
+                    assert_eq!(moved.transform(s.inverse()), position);
+                    assert_eq!(moved.canonical().0, canonical);
+                    assert_eq!(moved.to_state().winner(), position.to_state().winner());
+                    assert_eq!(moved.legal_moves().count(), position.legal_moves().count());
+                }
+
+                let moves: Vec<u16> = position.legal_moves().collect();
+                if moves.is_empty() {
+                    position = Position::empty(rules).unwrap();
+                    continue;
+                }
+                position = position.play(moves[i * 5 % moves.len()]);
+            }
+        }
+    }
+
+    #[test]
+    /// the corner openings are one canonical
+    /// position, so are the edges
+    fn position_canonical_openings() {
+        let empty = Position::empty(Rules::default()).unwrap();
+        let mut openings: Vec<Position> = (0..9).map(|tile| empty.play(tile).canonical().0).collect();
+        openings.sort_by_key(|position| position.x() | position.o());
+        openings.dedup();
+        assert_eq!(openings.len(), 3);
+    }
+}
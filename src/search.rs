@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::ai;
+use crate::models::{Cell, GameState, Player};
+use crate::position::Position;
+
+/// The score of a won game, wins found sooner
+/// score higher
+const WIN: f32 = 1_000_000.0;
+
+/// How a stored score relates to the real one,
+/// it may be cut short by alpha-beta
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+/// One transposition table entry
+#[derive(Copy, Clone, Debug)]
+struct Entry {
+    depth: u8,
+    score: f32,
+    bound: Bound,
+    tile: Option<u16>,
+}
+
+/// Negamax with alpha-beta pruning over
+/// bitboard positions. Positions seen before in
+/// the search are kept in a transposition table
+/// by their Zobrist hash. Marks expiring makes
+/// the game cyclic, a position that repeats one
+/// on the current line of play is scored as a
+/// draw. Positions deeper than the depth are
+/// scored by `ai::calculate_opportunity`.
+/// Scores that depend on the line of play, a
+/// repetition or the node budget running out,
+/// are not kept in the table
+#[derive(Clone, Debug, Default)]
+pub struct Searcher {
+    table: HashMap<u64, Entry>,
+    path: Vec<u64>,
+    nodes: u64,
+    budget: Option<u64>,
+    /// leaves scored by the line of play instead
+    /// of the position, see `negamax`
+    path_dependent: u64,
+}
+impl Searcher {
+    /// a searcher that stops deepening once it
+    /// has visited `budget` nodes, if given
+    pub fn new(budget: Option<u64>) -> Self {
+        Self { budget, ..Self::default() }
+    }
+
+    /// the nodes visited by the last search
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// the best move for the player to move and
+    /// its score, searching one ply deeper at a
+    /// time up to `depth`. A deeper search that
+    /// runs out of nodes is thrown away, the first
+    /// ply is always searched completely. Moves
+    /// with the same score are tried in an order
+    /// shuffled by `rng`
+    pub fn search<R: Rng + ?Sized>(&mut self, position: &Position, depth: u8, rng: &mut R) -> Option<(u16, f32)> {
+        let mut moves: Vec<u16> = position.legal_moves().collect();
+        moves.shuffle(rng);
+        self.nodes = 0;
+        self.path.clear();
+        self.path.push(position.zobrist());
+
+        let mut best = None;
+        for depth in 1..=depth.max(1) {
+            // the best move so far is searched first
+            if let Some((tile, _)) = best {
+                let i = moves.iter().position(|&t| t == tile).unwrap_or(0);
+                moves[..=i].rotate_right(1);
+            }
+
+            let mut alpha = -f32::INFINITY;
+            let mut current = None;
+            for &tile in &moves {
+                let score = -self.negamax(&position.play(tile), depth - 1, -f32::INFINITY, -alpha, 1);
+                if depth > 1 && self.out_of_nodes() {
+                    return best;
+                }
+                if score > alpha {
+                    alpha = score;
+                    current = Some((tile, score));
+                }
+            }
+            best = current;
+
+            // nothing deeper can change a forced result
+            if alpha.abs() >= WIN - f32::from(depth) {
+                break;
+            }
+        }
+        best
+    }
+
+    /// private function. The score of the position
+    /// for the player to move
+    fn negamax(&mut self, position: &Position, depth: u8, mut alpha: f32, beta: f32, ply: u16) -> f32 {
+        self.nodes += 1;
+        let winner = position.winner();
+        if winner != Cell::N {
+            // with gravity a falling mark can hand
+            // the line to the player to move
+            let score = WIN - f32::from(ply);
+            return if winner == position.to_move().cell() { score } else { -score };
+        }
+        let zobrist = position.zobrist();
+        if self.path.contains(&zobrist) {
+            self.path_dependent += 1;
+            return 0.0;
+        }
+        if position.legal_moves().len() == 0 {
+            // a full board without marks expiring
+            return 0.0;
+        }
+        if depth > 0 && self.out_of_nodes() {
+            self.path_dependent += 1;
+            return evaluate(position);
+        }
+        if depth == 0 {
+            return evaluate(position);
+        }
+
+        let original_alpha = alpha;
+        let mut first = None;
+        if let Some(entry) = self.table.get(&zobrist) {
+            if entry.depth >= depth {
+                let cut = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score >= beta,
+                    Bound::Upper => entry.score <= alpha,
+                };
+                if cut {
+                    return from_table(entry.score, ply);
+                }
+            }
+            first = entry.tile;
+        }
+
+        let path_dependent = self.path_dependent;
+        self.path.push(zobrist);
+        let mut best = -f32::INFINITY;
+        let mut best_tile = None;
+        let moves = first.into_iter().chain(position.legal_moves().filter(|&tile| Some(tile) != first));
+        for tile in moves {
+            let score = -self.negamax(&position.play(tile), depth - 1, -beta, -alpha, ply + 1);
+            if score > best {
+                best = score;
+                best_tile = Some(tile);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        self.path.pop();
+        if self.path_dependent != path_dependent {
+            return best;
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(zobrist, Entry { depth, score: to_table(best, ply), bound, tile: best_tile });
+        best
+    }
+
+    fn out_of_nodes(&self) -> bool {
+        self.budget.map_or(false, |budget| self.nodes >= budget)
+    }
+}
+
+/// private function. A score as the table keeps
+/// it, wins counted in plies from the position
+/// instead of from the root of the search
+fn to_table(score: f32, ply: u16) -> f32 {
+    if score > WIN / 2.0 {
+        score + f32::from(ply)
+    } else if score < -WIN / 2.0 {
+        score - f32::from(ply)
+    } else {
+        score
+    }
+}
+
+/// private function. The score of a table entry
+/// for a position `ply` plies from the root, see
+/// `to_table`
+fn from_table(score: f32, ply: u16) -> f32 {
+    if score > WIN / 2.0 {
+        score - f32::from(ply)
+    } else if score < -WIN / 2.0 {
+        score + f32::from(ply)
+    } else {
+        score
+    }
+}
+
+/// `ai::calculate_opportunity` on a bitboard,
+/// for the player to move
+pub fn evaluate(position: &Position) -> f32 {
+    let live = !position.expiring_tile().map_or(0, |tile| 1 << tile);
+    let (x, o) = (position.x() & live, position.o() & live);
+    let (mover, other) = match position.to_move() {
+        Player::X => (x, o),
+        Player::O => (o, x),
+    };
+    // the opportunity of the player who just moved
+    let counts = position
+    .lines()
+    .iter()
+    .map(|&line| ((other & line).count_ones() as i32, (mover & line).count_ones() as i32));
+    -ai::score_lines(counts, position.rules().board.win_length as i32)
+}
+
+/// Picks the move of the search AI, None if no
+/// move can be made. Boards too large for a
+/// bitboard fall back to the heuristic
+pub fn best_move<R: Rng + ?Sized>(game_state: &GameState, depth: u8, nodes: Option<u64>, rng: &mut R) -> Option<u16> {
+    match Position::from_state(game_state) {
+        Ok(position) => Searcher::new(nodes).search(&position, depth, rng).map(|(tile, _)| tile),
+        Err(_) => ai::turn(game_state, 0.0, rng),
+    }
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::{best_move, evaluate, Searcher, WIN};
+    use crate::ai::calculate_opportunity;
+    use crate::models::{Board, Cell, Expiry, GameState, Rules};
+    use crate::position::Position;
+    use crate::solver::{solved, Outcome};
+
+    #[test]
+    /// the bitboard evaluation scores positions the
+    /// same as the heuristic
+    fn search_evaluate() {
+        for rules in [Rules::default(), Rules::new(Board::square(4, 3), Expiry::PerPlayer(3))] {
+            let mut game_state = GameState::empty(rules);
+            for i in 0..200_usize {
+                let position = Position::from_state(&game_state).unwrap();
+                let opponent = game_state.to_move().cell();
+                assert_eq!(evaluate(&position), -calculate_opportunity(&game_state, opponent));
+
+                let moves = game_state.legal_moves();
+                if moves.is_empty() {
+                    game_state = GameState::empty(rules);
+                    continue;
+                }
+                game_state = game_state.turn(moves[i * 7 % moves.len()]).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn search_winning_move() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let position = Position::from_state(&GameState::from_array(array)).unwrap();
+        let (tile, score) = Searcher::new(None).search(&position, 4, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_eq!(tile, 0);
+        assert_eq!(score, WIN - 1.0);
+    }
+
+    #[test]
+    /// whenever the solver finds a short forced
+    /// win the search finds a winning move too
+    fn search_matches_solver() {
+        let solver = solved(Rules::default()).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut game_state = GameState::new();
+        let mut checked = 0;
+        for i in 0..300_usize {
+            if let Some(Outcome::Win(distance)) = solver.outcome(&game_state) {
+                if distance <= 5 {
+                    let tile = best_move(&game_state, 6, None, &mut rng).unwrap();
+                    let next = game_state.turn(tile).unwrap();
+                    assert!(matches!(solver.outcome(&next), Some(Outcome::Loss(_))), "{}", game_state);
+                    checked += 1;
+                }
+            }
+
+            let moves = game_state.legal_moves();
+            if moves.is_empty() {
+                game_state = GameState::new();
+                continue;
+            }
+            game_state = game_state.turn(moves[i * 5 % moves.len()]).unwrap();
+        }
+        assert!(checked > 10);
+    }
+
+    #[test]
+    /// a small node budget still gives a move,
+    /// searched at least one ply deep
+    fn search_node_budget() {
+        let position = Position::empty(Rules::default()).unwrap();
+        let mut searcher = Searcher::new(Some(50));
+        assert!(searcher.search(&position, 20, &mut StdRng::seed_from_u64(0)).is_some());
+        assert!(searcher.nodes() < 200);
+
+        let mut searcher = Searcher::new(None);
+        searcher.search(&position, 3, &mut StdRng::seed_from_u64(0));
+        assert!(searcher.nodes() > 50);
+    }
+
+    #[test]
+    /// the cyclic game does not make a deep
+    /// search loop forever
+    fn search_deep() {
+        let position = Position::empty(Rules::default()).unwrap();
+        let (_, score) = Searcher::new(None).search(&position, 9, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert!(score.abs() < WIN - 100.0);
+    }
+
+    #[test]
+    /// a full board is a draw, so searching the
+    /// whole classic game finds no win
+    fn search_classic() {
+        let position = Position::empty(Rules::classic()).unwrap();
+        let (_, score) = Searcher::new(None).search(&position, 9, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_eq!(score, 0.0);
+
+        let full = GameState::with_rules(Rules::classic(), vec![2, 8, 3, 5, 1, 6, 4, 7, 9]);
+        let position = Position::from_state(&full).unwrap();
+        assert_eq!(Searcher::new(None).search(&position, 4, &mut StdRng::seed_from_u64(0)), None);
+    }
+
+    #[test]
+    /// a win found through the table is counted
+    /// from where the position is reached
+    fn search_table_distance() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let position = Position::from_state(&GameState::from_array(array)).unwrap();
+        let mut searcher = Searcher::new(None);
+        assert_eq!(searcher.negamax(&position, 3, -f32::INFINITY, f32::INFINITY, 3), WIN - 4.0);
+        assert_eq!(searcher.negamax(&position, 3, -f32::INFINITY, f32::INFINITY, 1), WIN - 2.0);
+        assert_eq!(searcher.negamax(&position, 3, -f32::INFINITY, f32::INFINITY, 5), WIN - 6.0);
+    }
+
+    #[test]
+    /// a small node budget or a repetition does
+    /// not leave its scores in the table
+    fn search_table_path_dependent() {
+        let position = Position::empty(Rules::default()).unwrap();
+        let mut searcher = Searcher::new(Some(1));
+        searcher.negamax(&position, 3, -f32::INFINITY, f32::INFINITY, 1);
+        assert!(searcher.table.is_empty());
+
+        let mut searcher = Searcher::new(None);
+        searcher.path.push(position.play(4).zobrist());
+        searcher.negamax(&position, 2, -f32::INFINITY, f32::INFINITY, 1);
+        assert!(!searcher.table.contains_key(&position.zobrist()));
+        searcher.path.clear();
+        searcher.negamax(&position, 2, -f32::INFINITY, f32::INFINITY, 1);
+        assert!(searcher.table.contains_key(&position.zobrist()));
+    }
+
+    #[test]
+    /// a mark falling into place can complete the
+    /// line of the player who did not move
+    fn search_gravity_fall() {
+        let rules = Rules::new(Board::new(5, 3, 3), Expiry::Global(8)).with_gravity();
+        let mut game_state = GameState::empty(rules);
+        for column in [0, 4, 3, 1, 3, 2, 4, 0] {
+            game_state = game_state.drop_mark(column).unwrap();
+        }
+        // O dropping in column 1 makes X's mark fall
+        // into a line
+        let falling = rules.drop_tile(&game_state, 1).unwrap();
+        let position = Position::from_state(&game_state).unwrap();
+        assert_eq!(position.play(falling).winner(), Cell::X);
+
+        let mut searcher = Searcher::new(None);
+        let (tile, score) = searcher.search(&position, 1, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_ne!(tile, falling);
+        assert!(score > -WIN / 2.0);
+        assert_eq!(searcher.negamax(&position.play(falling), 0, -WIN, WIN, 1), WIN - 1.0);
+    }
+}
@@ -0,0 +1,410 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::models::{Expiry, GameState, Rules};
+
+/// Positions explored before giving up on a
+/// ruleset, the classic game has about 120 000
+pub const DEFAULT_LIMIT: usize = 2_000_000;
+
+/// The most positions `solved` lets a ruleset
+/// have by `max_positions`, a little above the
+/// 3x3 game. Bigger rules take seconds to
+/// minutes to solve and are refused at once
+pub const SOLVED_LIMIT: usize = 200_000;
+
+/// The result of a position with perfect play,
+/// seen from the player to move. Distances are
+/// in plies until the game is won
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Win(u16),
+    Loss(u16),
+    /// neither side can force a win, the game
+    /// repeats forever
+    Draw,
+}
+impl Outcome {
+    /// the same outcome from the other player's
+    /// side, one ply earlier
+    pub(crate) fn previous(&self) -> Self {
+        match self {
+            Outcome::Win(d) => Outcome::Loss(d + 1),
+            Outcome::Loss(d) => Outcome::Win(d + 1),
+            Outcome::Draw => Outcome::Draw,
+        }
+    }
+}
+
+/// Every position reachable from the empty
+/// board labeled by retrograde analysis.
+/// Marks expiring means positions repeat, so
+/// instead of searching forward the solver
+/// starts from the won positions and works
+/// backwards, whatever is never reached from
+/// them is a draw by repetition
+#[derive(Clone, Debug)]
+pub struct Solver {
+    rules: Rules,
+    index: HashMap<Vec<u16>, usize>,
+    outcomes: Vec<Outcome>,
+}
+impl Solver {
+    /// solves the rules with the default limit
+    pub fn solve(rules: Rules) -> Option<Self> {
+        Solver::solve_with_limit(rules, DEFAULT_LIMIT)
+    }
+
+    /// solves the rules, or None if there are
+    /// more than `limit` reachable positions
+    pub fn solve_with_limit(rules: Rules, limit: usize) -> Option<Self> {
+        let mut index = HashMap::new();
+        let mut children: Vec<Vec<usize>> = Vec::new();
+        let mut states = Vec::new();
+
+        let start = GameState::empty(rules);
+        index.insert(key(&start), 0);
+        states.push(start);
+
+        // forward pass, every reachable position
+        // and the positions each move leads to
+        let mut next = 0;
+        while next < states.len() {
+            let state = states[next].clone();
+            let mut list = Vec::new();
+            for tile in state.legal_moves() {
+                let child = state.turn(tile).ok()?;
+                let child_key = key(&child);
+                let id = match index.get(&child_key) {
+                    Some(&id) => id,
+                    None => {
+                        if states.len() >= limit {
+                            return None;
+                        }
+                        let id = states.len();
+                        index.insert(child_key.clone(), id);
+                        states.push(GameState::with_rules(rules, child_key));
+                        id
+                    },
+                };
+                list.push(id);
+            }
+            children.push(list);
+            next += 1;
+        }
+
+        let mut parents = vec![Vec::new(); states.len()];
+        for (id, list) in children.iter().enumerate() {
+            for &child in list {
+                parents[child].push(id);
+            }
+        }
+
+        // backward pass, starting from the won
+        // positions. With gravity a falling mark can
+        // give the line to the player to move
+        let mut outcomes = vec![None; states.len()];
+        let mut remaining: Vec<usize> = children.iter().map(|list| list.len()).collect();
+        let mut queue = VecDeque::new();
+        for (id, state) in states.iter().enumerate() {
+            if let Some(win) = state.win() {
+                outcomes[id] = Some(if win.winner == state.to_move().cell() {
+                    Outcome::Win(0)
+                } else {
+                    Outcome::Loss(0)
+                });
+                queue.push_back(id);
+            }
+        }
+
+        while let Some(id) = queue.pop_front() {
+            let outcome = outcomes[id].unwrap();
+            for &parent in &parents[id] {
+                if outcomes[parent].is_some() {
+                    continue;
+                }
+                match outcome {
+                    Outcome::Loss(_) => {
+                        outcomes[parent] = Some(outcome.previous());
+                        queue.push_back(parent);
+                    },
+                    _ => {
+                        remaining[parent] -= 1;
+                        if remaining[parent] == 0 {
+                            outcomes[parent] = Some(outcome.previous());
+                            queue.push_back(parent);
+                        }
+                    },
+                }
+            }
+        }
+
+        Some(Self {
+            rules,
+            index,
+            outcomes: outcomes.into_iter().map(|o| o.unwrap_or(Outcome::Draw)).collect(),
+        })
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// number of reachable positions
+    pub fn len(&self) -> usize {
+        self.outcomes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outcomes.is_empty()
+    }
+
+    /// the outcome of a position, None if it is
+    /// played with other rules or is not reachable
+    pub fn outcome(&self, game_state: &GameState) -> Option<Outcome> {
+        if game_state.rules != self.rules {
+            return None;
+        }
+        self.index.get(&key(game_state)).map(|&id| self.outcomes[id])
+    }
+
+    /// the moves that keep the best outcome for the
+    /// player to move: the fastest win, otherwise a
+    /// draw, otherwise the slowest loss
+    pub fn best_moves(&self, game_state: &GameState) -> Vec<u16> {
+        let mut best: Vec<(u16, Outcome)> = Vec::new();
+        for tile in game_state.legal_moves() {
+            let outcome = match game_state.turn(tile).ok().and_then(|new| self.outcome(&new)) {
+                Some(outcome) => outcome.previous(),
+                None => continue,
+            };
+            match best.first() {
+                Some(&(_, current)) if rank(outcome) < rank(current) => continue,
+                Some(&(_, current)) if rank(outcome) > rank(current) => best.clear(),
+                _ => {},
+            }
+            best.push((tile, outcome));
+        }
+        best.into_iter().map(|(tile, _)| tile).collect()
+    }
+}
+
+/// orders outcomes from worst to best for the
+/// player to move
+fn rank(outcome: Outcome) -> i32 {
+    match outcome {
+        Outcome::Loss(d) => d as i32 - i32::from(u16::MAX) * 2,
+        Outcome::Draw => 0,
+        Outcome::Win(d) => i32::from(u16::MAX) - d as i32,
+    }
+}
+
+/// the array with the oldest mark renumbered to
+/// 1 or 2, keeping the parity of every move,
+/// so the same position at any point of an
+/// infinite game has the same key
+fn key(game_state: &GameState) -> Vec<u16> {
+    if game_state.rules.expiry == Expiry::Never {
+        // the order of marks that never expire does
+        // not matter, each player's are numbered in
+        // tile order
+        let mut next = [1, 2];
+        return game_state.array.iter().map(|&n| {
+            if n == 0 {
+                return 0;
+            }
+            let side = &mut next[usize::from(n % 2 == 0)];
+            *side += 2;
+            *side - 2
+        }).collect();
+    }
+    let oldest = game_state.array.iter().copied().filter(|&n| n != 0).min().unwrap_or(1);
+    let offset = (oldest - 1) / 2 * 2;
+    game_state.array.iter().map(|&n| if n == 0 { 0 } else { n - offset }).collect()
+}
+
+thread_local! {
+    static SOLVED: RefCell<HashMap<Rules, Option<Rc<Solver>>>> = RefCell::new(HashMap::new());
+}
+
+/// an upper bound on the positions of the
+/// rules, checked before solving: every ordered
+/// placement of as many marks as the window
+/// keeps, twice for the parity of the oldest
+/// mark. Every way to fill the board when marks
+/// never expire
+pub fn max_positions(rules: &Rules) -> usize {
+    let tiles = rules.board.len();
+    let marks = match rules.expiry {
+        Expiry::Never => return 3_usize.saturating_pow(tiles as u32),
+        Expiry::Global(window) => usize::from(window),
+        Expiry::PerPlayer(window) => usize::from(window) * 2,
+    };
+    let mut placements: usize = 1;
+    let mut total: usize = 1;
+    for placed in 0..marks.min(tiles) {
+        placements = placements.saturating_mul(tiles - placed);
+        total = total.saturating_add(placements);
+    }
+    total.saturating_mul(2)
+}
+
+/// the solver for the rules, solved the first
+/// time it is asked for and kept afterwards.
+/// None if the rules are too big to solve,
+/// rules with more than `SOLVED_LIMIT` possible
+/// positions are not tried
+pub fn solved(rules: Rules) -> Option<Rc<Solver>> {
+    if max_positions(&rules) > SOLVED_LIMIT {
+        return None;
+    }
+    SOLVED.with(|solved| {
+        solved
+        .borrow_mut()
+        .entry(rules)
+        .or_insert_with(|| Solver::solve(rules).map(Rc::new))
+        .clone()
+    })
+}
+
+/// the moves that play perfectly from the
+/// position, empty if its rules can not be solved
+pub fn best_moves(game_state: &GameState) -> Vec<u16> {
+    solved(game_state.rules).map_or(Vec::new(), |solver| solver.best_moves(game_state))
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::{best_moves, max_positions, solved, key, Outcome, Solver, SOLVED_LIMIT};
+    use crate::models::{Board, Cell, Expiry, GameState, Player, Rules};
+
+    #[test]
+    fn solver_winning_move() {
+        let array = [
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let game_state = GameState::from_array(array);
+        assert_eq!(best_moves(&game_state), vec![0]);
+        assert_eq!(solved(Rules::default()).unwrap().outcome(&game_state), Some(Outcome::Win(1)));
+    }
+
+    #[test]
+    /// the position after a win is lost for the
+    /// player to move
+    fn solver_won_position() {
+        let array = [
+            6, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ];
+        let game_state = GameState::from_array(array);
+        let solver = solved(Rules::default()).unwrap();
+        assert_eq!(solver.outcome(&game_state), Some(Outcome::Loss(0)));
+        assert!(solver.best_moves(&game_state).is_empty());
+    }
+
+    #[test]
+    /// the block is the only move that does not
+    /// lose on the next turn
+    fn solver_must_block() {
+        let array = [
+            1, 2, 0,
+            0, 3, 0,
+            0, 0, 0,
+        ];
+        let game_state = GameState::from_array(array);
+        let solver = solved(Rules::default()).unwrap();
+        assert!(solver.len() > 1000);
+        assert_eq!(solver.best_moves(&game_state), vec![8]);
+    }
+
+    #[test]
+    /// every position has the same key at any
+    /// point of the game, and rebuilding it from
+    /// the key keeps the expiring mark
+    fn solver_key_renumbering() {
+        let early = GameState::from_array([
+            0, 15, 0,
+            13, 11, 14,
+            12, 16, 0,
+        ]);
+        let late = GameState::from_array([
+            0, 25, 0,
+            23, 21, 24,
+            22, 26, 0,
+        ]);
+        assert_eq!(key(&early), key(&late));
+
+        let rebuilt = GameState::from_array([
+            0, 5, 0,
+            3, 1, 4,
+            2, 6, 0,
+        ]);
+        assert_eq!(key(&early), rebuilt.array);
+        assert_eq!(rebuilt.state, early.state);
+    }
+
+    #[test]
+    /// classic tic-tac-toe is a draw and every
+    /// position of it can be solved
+    fn solver_classic() {
+        let solver = solved(Rules::classic()).unwrap();
+        assert_eq!(solver.len(), 5478);
+        assert_eq!(solver.outcome(&GameState::empty(Rules::classic())), Some(Outcome::Draw));
+
+        let corner = GameState::empty(Rules::classic()).turn(0).unwrap();
+        assert_eq!(solver.best_moves(&corner), vec![4]);
+    }
+
+    #[test]
+    /// a mark falling into place can win the game
+    /// for the player to move
+    fn solver_gravity_fall() {
+        let rules = Rules::new(Board::new(4, 3, 3), Expiry::Global(7)).with_gravity();
+        let mut game_state = GameState::empty(rules);
+        for column in [1, 2, 2, 1, 1, 3, 3] {
+            game_state = game_state.drop_mark(column).unwrap();
+        }
+        // X dropping in column 3 removes the mark
+        // under O's and it falls into O's line
+        let fallen = game_state.drop_mark(3).unwrap();
+        assert_eq!(fallen.winner(), Cell::O);
+        assert_eq!(fallen.to_move(), Player::O);
+
+        let solver = Solver::solve(rules).unwrap();
+        assert_eq!(solver.outcome(&fallen), Some(Outcome::Win(0)));
+        // the fall is no win for X, who can not
+        // stop O either
+        assert_eq!(solver.outcome(&game_state), Some(Outcome::Loss(1)));
+    }
+
+    #[test]
+    fn solver_limit() {
+        let rules = Rules::new(Board::square(4, 4), Expiry::Global(8));
+        assert!(Solver::solve_with_limit(rules, 1000).is_none());
+    }
+
+    #[test]
+    /// the bound holds for the rules that can be
+    /// solved and refuses bigger boards at once
+    fn solver_max_positions() {
+        for rules in [Rules::default(), Rules::classic(), Rules::new(Board::new(4, 3, 3), Expiry::PerPlayer(2))] {
+            assert!(max_positions(&rules) <= SOLVED_LIMIT);
+            assert!(solved(rules).unwrap().len() <= max_positions(&rules));
+        }
+        let rules = Rules::sized(4, 4, 3, None, None, None);
+        assert!(max_positions(&rules) > SOLVED_LIMIT);
+        assert!(solved(rules).is_none());
+    }
+}
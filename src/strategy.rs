@@ -0,0 +1,362 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rand::RngCore;
+use rand::seq::{IteratorRandom, SliceRandom};
+
+use crate::ai::{self, Level};
+use crate::mcts::{self, MctsConfig};
+use crate::models::GameState;
+use crate::search;
+use crate::solver::{self, Outcome};
+
+/// A way of picking moves. The built in ones
+/// are `RandomMove`, `Heuristic`, `Search`,
+/// `Perfect` and `MonteCarlo`, other crates can
+/// add their own with `register`
+pub trait Strategy {
+    /// the name the strategy is registered under,
+    /// with its setting after a colon if it has
+    /// one, e.g. "search:6"
+    fn name(&self) -> String;
+
+    /// the tile to play for the player to move,
+    /// None if no move can be made. All randomness
+    /// should come from `rng`, so a seeded rng
+    /// always picks the same moves
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16>;
+
+    /// the score of every legal move for the
+    /// player to move, higher is better. None for
+    /// strategies that do not score their moves
+    fn evaluate(&self, _game_state: &GameState) -> Option<Vec<(u16, f32)>> {
+        None
+    }
+}
+
+/// plays any legal move
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct RandomMove;
+impl Strategy for RandomMove {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16> {
+        game_state.legal_moves().into_iter().choose(rng)
+    }
+}
+
+/// looks one move ahead, `temperature` is the
+/// amount of random noise added to the score
+/// of every move
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Heuristic {
+    pub temperature: f32,
+}
+impl Default for Heuristic {
+    /// the same as `strategy("heuristic")`
+    fn default() -> Self {
+        Heuristic { temperature: 1.0 }
+    }
+}
+impl Strategy for Heuristic {
+    fn name(&self) -> String {
+        format!("heuristic:{}", self.temperature)
+    }
+
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16> {
+        ai::turn(game_state, self.temperature, rng)
+    }
+
+    fn evaluate(&self, game_state: &GameState) -> Option<Vec<(u16, f32)>> {
+        Some(ai::evaluate_moves(game_state))
+    }
+}
+
+/// searches `depth` moves ahead, see
+/// `search::Searcher`. A node limit is
+/// written after the depth in its name,
+/// e.g. "search:6,10000"
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Search {
+    pub depth: u8,
+    pub nodes: Option<u64>,
+}
+impl Strategy for Search {
+    fn name(&self) -> String {
+        match self.nodes {
+            Some(nodes) => format!("search:{},{}", self.depth, nodes),
+            None => format!("search:{}", self.depth),
+        }
+    }
+
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16> {
+        search::best_move(game_state, self.depth, self.nodes, rng)
+    }
+}
+
+/// plays one of the best moves found by the
+/// solver. Rules too big to be solved fall back
+/// to the heuristic without noise
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Perfect;
+impl Strategy for Perfect {
+    fn name(&self) -> String {
+        "perfect".to_string()
+    }
+
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16> {
+        match solver::best_moves(game_state).choose(rng) {
+            Some(&tile) => Some(tile),
+            None => ai::turn(game_state, 0.0, rng),
+        }
+    }
+
+    /// wins score from 1000 down and losses from
+    /// -1000 up by how many moves they take, draws
+    /// score 0
+    fn evaluate(&self, game_state: &GameState) -> Option<Vec<(u16, f32)>> {
+        let solver = solver::solved(game_state.rules)?;
+        game_state
+        .legal_moves()
+        .into_iter()
+        .map(|tile| {
+            let new = game_state.turn(tile).ok()?;
+            let score = match solver.outcome(&new)?.previous() {
+                Outcome::Win(d) => 1000.0 - f32::from(d),
+                Outcome::Draw => 0.0,
+                Outcome::Loss(d) => f32::from(d) - 1000.0,
+            };
+            Some((tile, score))
+        })
+        .collect()
+    }
+}
+
+/// Monte Carlo tree search, see `mcts`
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub struct MonteCarlo(pub MctsConfig);
+impl Strategy for MonteCarlo {
+    fn name(&self) -> String {
+        format!("mcts:{}", self.0.iterations)
+    }
+
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16> {
+        mcts::best_move(game_state, self.0, rng)
+    }
+}
+
+/// the levels play as their built in strategy
+impl Strategy for Level {
+    fn name(&self) -> String {
+        self.strategy().name()
+    }
+
+    fn choose_move(&self, game_state: &GameState, rng: &mut dyn RngCore) -> Option<u16> {
+        self.strategy().choose_move(game_state, rng)
+    }
+
+    fn evaluate(&self, game_state: &GameState) -> Option<Vec<(u16, f32)>> {
+        self.strategy().evaluate(game_state)
+    }
+}
+impl Level {
+    /// private function. The built in strategy
+    /// of the level
+    fn strategy(&self) -> Box<dyn Strategy> {
+        match *self {
+            Level::Heuristic(temperature) => Box::new(Heuristic { temperature }),
+            Level::Perfect => Box::new(Perfect),
+            Level::Search { depth, nodes } => Box::new(Search { depth, nodes }),
+            Level::Mcts(config) => Box::new(MonteCarlo(config)),
+        }
+    }
+}
+
+/// Builds a strategy from the setting written
+/// after the colon of its name, an empty string
+/// if there is none. None if the setting is
+/// not valid
+pub type Factory = Rc<dyn Fn(&str) -> Option<Box<dyn Strategy>>>;
+
+thread_local! {
+    static REGISTRY: RefCell<HashMap<String, Factory>> = RefCell::new(built_ins());
+}
+
+/// private function. The factories of the
+/// built in strategies, settings left out take
+/// their defaults
+fn built_ins() -> HashMap<String, Factory> {
+    fn setting<T: std::str::FromStr>(setting: &str, default: T) -> Option<T> {
+        if setting.is_empty() { Some(default) } else { setting.parse().ok() }
+    }
+    let mut factories: HashMap<String, Factory> = HashMap::new();
+    factories.insert("random".to_string(), Rc::new(|_: &str| Some(Box::new(RandomMove) as Box<dyn Strategy>)));
+    factories.insert("heuristic".to_string(), Rc::new(|s: &str| {
+        Some(Box::new(Heuristic { temperature: setting(s, 1.0)? }) as Box<dyn Strategy>)
+    }));
+    factories.insert("search".to_string(), Rc::new(|s: &str| {
+        let (depth, nodes) = match s.split_once(',') {
+            Some((depth, nodes)) => (depth, Some(nodes.trim().parse().ok()?)),
+            None => (s, None),
+        };
+        Some(Box::new(Search { depth: setting(depth.trim(), 6)?, nodes }) as Box<dyn Strategy>)
+    }));
+    factories.insert("perfect".to_string(), Rc::new(|_: &str| Some(Box::new(Perfect) as Box<dyn Strategy>)));
+    factories.insert("mcts".to_string(), Rc::new(|s: &str| {
+        // no iterations would find no move
+        let iterations = setting(s, MctsConfig::default().iterations).filter(|&iterations| iterations > 0)?;
+        Some(Box::new(MonteCarlo(MctsConfig { iterations, ..MctsConfig::default() })) as Box<dyn Strategy>)
+    }));
+    factories
+}
+
+/// Adds a strategy under `name`, replacing any
+/// strategy already registered under it
+pub fn register<F>(name: &str, factory: F)
+where
+    F: Fn(&str) -> Option<Box<dyn Strategy>> + 'static,
+{
+    REGISTRY.with(|registry| registry.borrow_mut().insert(name.to_string(), Rc::new(factory)));
+}
+
+/// The strategy named by `spec`, a registered
+/// name optionally followed by a colon and its
+/// setting, e.g. "mcts:5000". None if the name
+/// is unknown or the setting is not valid
+pub fn strategy(spec: &str) -> Option<Box<dyn Strategy>> {
+    let (name, setting) = spec.split_once(':').unwrap_or((spec, ""));
+    let factory = REGISTRY.with(|registry| registry.borrow().get(name.trim()).cloned())?;
+    factory(setting.trim())
+}
+
+/// the registered names in alphabetical order
+pub fn names() -> Vec<String> {
+    let mut names: Vec<String> = REGISTRY.with(|registry| registry.borrow().keys().cloned().collect());
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use rand::{RngCore, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use super::{names, register, strategy, Heuristic, Perfect, RandomMove, Search, Strategy};
+    use crate::ai::Level;
+    use crate::models::{Board, Expiry, GameState, Rules};
+
+    /// always plays the lowest free tile
+    struct First;
+    impl Strategy for First {
+        fn name(&self) -> String {
+            "first".to_string()
+        }
+
+        fn choose_move(&self, game_state: &GameState, _rng: &mut dyn RngCore) -> Option<u16> {
+            game_state.legal_moves().first().copied()
+        }
+    }
+
+    #[test]
+    fn strategy_names() {
+        for spec in ["random", "heuristic:0.5", "search:4", "perfect", "mcts:200"] {
+            assert_eq!(strategy(spec).unwrap().name(), spec);
+        }
+        assert_eq!(strategy("search").unwrap().name(), "search:6");
+        let limited = Search { depth: 4, nodes: Some(10_000) };
+        assert_eq!(limited.name(), "search:4,10000");
+        assert_eq!(strategy(&limited.name()).unwrap().name(), limited.name());
+        assert!(strategy("search:4,many").is_none());
+        assert_eq!(Heuristic::default().name(), strategy("heuristic").unwrap().name());
+        assert!(strategy("search:deep").is_none());
+        assert!(strategy("unknown").is_none());
+        assert!(strategy("mcts:0").is_none());
+        assert_eq!(Level::Heuristic(2.0).name(), "heuristic:2");
+    }
+
+    #[test]
+    fn strategy_register() {
+        register("first", |_| Some(Box::new(First)));
+        assert!(names().contains(&"first".to_string()));
+        let first = strategy("first").unwrap();
+        let game_state = GameState::from_array([1, 2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(first.choose_move(&game_state, &mut StdRng::seed_from_u64(0)), Some(2));
+        assert_eq!(first.evaluate(&game_state), None);
+    }
+
+    #[test]
+    fn strategy_winning_move() {
+        let game_state = GameState::from_array([
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ]);
+        for spec in ["heuristic:0", "search:4", "perfect", "mcts:1000"] {
+            let mut rng = StdRng::seed_from_u64(0);
+            assert_eq!(strategy(spec).unwrap().choose_move(&game_state, &mut rng), Some(0), "{}", spec);
+        }
+        let tile = RandomMove.choose_move(&game_state, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert!(game_state.legal_moves().contains(&tile));
+    }
+
+    #[test]
+    /// the strategies find the win through the
+    /// layers of a cube
+    fn strategy_cube() {
+        let mut game_state = GameState::empty(Rules::cube(3, None, None, None));
+        for tile in [0, 1, 13, 2] {
+            game_state = game_state.turn(tile).unwrap();
+        }
+        for spec in ["heuristic:0", "search:4", "mcts:1000"] {
+            let mut rng = StdRng::seed_from_u64(0);
+            assert_eq!(strategy(spec).unwrap().choose_move(&game_state, &mut rng), Some(26), "{}", spec);
+        }
+    }
+
+    #[test]
+    /// the strategies drop in the column that
+    /// completes the row with gravity
+    fn strategy_gravity() {
+        let rules = Rules::new(Board::new(5, 4, 3), Expiry::Global(6)).with_gravity();
+        let mut game_state = GameState::empty(rules);
+        for column in [1, 1, 2, 2] {
+            game_state = game_state.drop_mark(column).unwrap();
+        }
+        for spec in ["heuristic:0", "search:4", "perfect", "mcts:1000"] {
+            let mut rng = StdRng::seed_from_u64(0);
+            let tile = strategy(spec).unwrap().choose_move(&game_state, &mut rng);
+            assert!(tile == Some(15) || tile == Some(18), "{} played {:?}", spec, tile);
+        }
+    }
+
+    #[test]
+    /// perfect play on rules too big to solve
+    /// falls back to the heuristic without trying
+    fn strategy_perfect_unsolvable() {
+        let game_state = GameState::empty(Rules::sized(4, 4, 3, None, None, None));
+        let start = Instant::now();
+        let tile = Perfect.choose_move(&game_state, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert!(game_state.legal_moves().contains(&tile));
+        assert_eq!(Perfect.evaluate(&game_state), None);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn strategy_evaluate() {
+        let game_state = GameState::from_array([
+            0, 1, 3,
+            2, 0, 0,
+            4, 0, 5,
+        ]);
+        let scores = Perfect.evaluate(&game_state).unwrap();
+        let best = scores.iter().max_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+        assert_eq!(*best, (0, 999.0));
+        let heuristic = Heuristic { temperature: 0.0 }.evaluate(&game_state).unwrap();
+        assert_eq!(heuristic.len(), scores.len());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::models::{Board, Rules};
+
+/// The symmetries of a board, the dihedral group
+/// D4 of the square. Rectangular boards only have
+/// the ones that keep their width and height
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Symmetry {
+    Identity,
+    /// a quarter turn clockwise
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// mirrored left to right
+    FlipHorizontal,
+    /// mirrored top to bottom
+    FlipVertical,
+    /// mirrored along the diagonal from the top left
+    FlipDiagonal,
+    /// mirrored along the diagonal from the top right
+    FlipAntiDiagonal,
+}
+impl Symmetry {
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::FlipHorizontal,
+        Symmetry::FlipVertical,
+        Symmetry::FlipDiagonal,
+        Symmetry::FlipAntiDiagonal,
+    ];
+
+    /// the symmetries that map the board onto
+    /// itself: all eight for square boards, four
+    /// for the others
+    pub fn of(board: &Board) -> impl Iterator<Item = Symmetry> {
+        let square = board.width == board.height;
+        IntoIterator::into_iter(Symmetry::ALL).filter(move |symmetry| square || !symmetry.swaps_axes())
+    }
+
+    /// the symmetries of the board that also keep
+    /// the game the same: with gravity only the
+    /// ones that leave the bottom row at the bottom
+    pub fn of_rules(rules: &Rules) -> impl Iterator<Item = Symmetry> {
+        let gravity = rules.gravity;
+        Symmetry::of(&rules.board)
+        .filter(move |symmetry| !gravity || matches!(symmetry, Symmetry::Identity | Symmetry::FlipHorizontal))
+    }
+
+    /// true for the symmetries that turn rows
+    /// into columns
+    pub fn swaps_axes(&self) -> bool {
+        matches!(
+            self,
+            Symmetry::Rotate90 | Symmetry::Rotate270 | Symmetry::FlipDiagonal | Symmetry::FlipAntiDiagonal
+        )
+    }
+
+    /// the symmetry that undoes this one
+    pub fn inverse(&self) -> Symmetry {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            symmetry => *symmetry,
+        }
+    }
+
+    /// where a tile ends up. Symmetries that swap
+    /// the axes are only valid on square boards.
+    /// Every layer of a cubic board is moved the
+    /// same way, which keeps the lines through
+    /// the layers
+    pub fn apply(&self, board: &Board, tile: u16) -> u16 {
+        let (width, height) = (board.width, board.height);
+        let (col, row, layer) = board.coordinates(tile);
+        let (last_row, last_col) = (height - 1, width - 1);
+        let (row, col) = match self {
+            Symmetry::Identity => (row, col),
+            Symmetry::Rotate90 => (col, last_row - row),
+            Symmetry::Rotate180 => (last_row - row, last_col - col),
+            Symmetry::Rotate270 => (last_col - col, row),
+            Symmetry::FlipHorizontal => (row, last_col - col),
+            Symmetry::FlipVertical => (last_row - row, col),
+            Symmetry::FlipDiagonal => (col, row),
+            Symmetry::FlipAntiDiagonal => (last_col - col, last_row - row),
+        };
+        board.tile(col, row, layer).expect("symmetries keep tiles on the board")
+    }
+
+    /// every tile of a bitboard moved by the
+    /// symmetry
+    pub fn apply_mask(&self, board: &Board, mask: u64) -> u64 {
+        let mut mask = mask;
+        let mut moved = 0;
+        while mask != 0 {
+            let tile = mask.trailing_zeros() as u16;
+            moved |= 1 << self.apply(board, tile);
+            mask &= mask - 1;
+        }
+        moved
+    }
+}
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use super::Symmetry;
+    use crate::models::Board;
+
+    #[test]
+    fn symmetry_tiles() {
+        let board = Board::default();
+        let corners: Vec<u16> = Symmetry::ALL.iter().map(|s| s.apply(&board, 0)).collect();
+        assert_eq!(corners, vec![0, 2, 8, 6, 2, 6, 0, 8]);
+        let edge: Vec<u16> = Symmetry::ALL.iter().map(|s| s.apply(&board, 1)).collect();
+        assert_eq!(edge, vec![1, 5, 7, 3, 1, 7, 3, 5]);
+        assert!(Symmetry::ALL.iter().all(|s| s.apply(&board, 4) == 4));
+    }
+
+    #[test]
+    /// every symmetry is a permutation of the
+    /// tiles that its inverse undoes, and maps
+    /// win lines to win lines
+    fn symmetry_permutations() {
+        for board in [Board::default(), Board::square(4, 3), Board::new(5, 3, 3), Board::cube(3, 3), Board::cube(4, 4)] {
+            let mut lines: Vec<Vec<u16>> = board.lines();
+            for line in lines.iter_mut() {
+                line.sort_unstable();
+            }
+            lines.sort();
+
+            for symmetry in Symmetry::of(&board) {
+                let mut tiles: Vec<u16> = (0..board.len() as u16).map(|t| symmetry.apply(&board, t)).collect();
+                for tile in 0..board.len() as u16 {
+                    assert_eq!(symmetry.inverse().apply(&board, tiles[tile as usize]), tile);
+                }
+                tiles.sort_unstable();
+                assert_eq!(tiles, (0..board.len() as u16).collect::<Vec<u16>>());
+
+                let mut moved: Vec<Vec<u16>> = lines
+                .iter()
+                .map(|line| {
+                    let mut line: Vec<u16> = line.iter().map(|&t| symmetry.apply(&board, t)).collect();
+                    line.sort_unstable();
+                    line
+                })
+                .collect();
+                moved.sort();
+                assert_eq!(moved, lines, "{:?}", symmetry);
+            }
+        }
+        assert_eq!(Symmetry::of(&Board::new(5, 3, 3)).count(), 4);
+    }
+}
@@ -0,0 +1,521 @@
+use wasm_bindgen::prelude::*;
+
+use crate::error::GameError;
+use crate::models::{Board, Cell, Expiry, GameState, Player, Rules, Status, RENUMBER_AT};
+
+/// The tiles of a local board, which is also
+/// the number of local boards
+const SIZE: u16 = 9;
+
+/// Ultimate tic-tac-toe: nine 3x3 games on a
+/// 3x3 grid. The tile of every move sends the
+/// opponent to the local board with the same
+/// index, unless that board is already won or
+/// full, then they may play on any open board.
+/// Winning three local boards in a row wins
+/// the game.
+/// Marks can expire inside every local board,
+/// counted on that board alone but by the same
+/// rule as the main game: once a board holds
+/// `window` marks its oldest one is expiring,
+/// it can not be played on and does not count
+/// for a line, and the next move on that board
+/// removes it. A board is decided by its live
+/// marks, with a window per player they depend
+/// on who is to move, as in the main game.
+/// The array holds the 81 move numbers board
+/// after board, the boards and their tiles in
+/// reading order
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Debug)]
+pub struct Ultimate {
+    expiry: Expiry,
+    first: Player,
+    array: Vec<u16>,
+    last: u16,
+    boards: Vec<GameState>,
+}
+impl Ultimate {
+    /// an empty game
+    pub fn new(expiry: Expiry, first: Player) -> Self {
+        let mut game = Self {
+            expiry,
+            first,
+            array: vec![0; usize::from(SIZE * SIZE)],
+            last: 0,
+            boards: Vec::new(),
+        };
+        game.update();
+        game
+    }
+
+    /// resumes a game from its array, rejecting
+    /// arrays of the wrong length, move numbers
+    /// used twice and local boards with more marks
+    /// than the window keeps
+    pub fn load(expiry: Expiry, first: Player, array: &[u16]) -> Result<Self, GameError> {
+        let expected = usize::from(SIZE * SIZE);
+        if array.len() != expected {
+            return Err(GameError::WrongLength { expected, found: array.len() });
+        }
+        let mut numbers: Vec<u16> = array.iter().copied().filter(|&n| n != 0).collect();
+        numbers.sort_unstable();
+        if let Some(pair) = numbers.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(GameError::DuplicateMove(pair[0]));
+        }
+
+        let mut game = Self::new(expiry, first);
+        game.array = array.to_vec();
+        game.last = numbers.last().copied().unwrap_or(0);
+        if game.last >= RENUMBER_AT {
+            game.renumber();
+        }
+        game.update();
+
+        let window = expiry.window();
+        for local in &game.boards {
+            let marks = |player: Player| local.array.iter().filter(|&&n| n != 0 && local.rules.player(n) == player).count();
+            let count = match expiry {
+                Expiry::Global(_) | Expiry::Never => marks(Player::X) + marks(Player::O),
+                Expiry::PerPlayer(_) => marks(Player::X).max(marks(Player::O)),
+            };
+            if count > window as usize {
+                return Err(GameError::TooManyMarks { count, window });
+            }
+        }
+        Ok(game)
+    }
+
+    /// the rules of every local board, the
+    /// expiry is counted on each board alone
+    pub fn rules(&self) -> Rules {
+        Rules::new(Board::default(), self.expiry).with_first(self.first)
+    }
+
+    /// a local board. Its array holds the move
+    /// numbers of the whole game
+    pub fn board(&self, board: u16) -> &GameState {
+        &self.boards[board as usize]
+    }
+
+    /// the mark on a tile of the array
+    pub fn get(&self, index: u16) -> Cell {
+        self.board(index / SIZE).get(index % SIZE)
+    }
+
+    /// the player who makes the next move
+    pub fn to_move(&self) -> Player {
+        self.rules().player(self.last + 1)
+    }
+
+    /// true while a local board can be played on
+    pub fn is_open(&self, board: u16) -> bool {
+        self.board(board).status() == Status::InProgress
+    }
+
+    /// the player who has won three local boards
+    /// in a row, or None
+    pub fn winner(&self) -> Cell {
+        Board::default()
+        .lines()
+        .into_iter()
+        .map(|line| {
+            let cell = self.board(line[0]).winner();
+            if line.iter().all(|&board| self.board(board).winner() == cell) { cell } else { Cell::N }
+        })
+        .find(|&cell| cell != Cell::N)
+        .unwrap_or(Cell::N)
+    }
+
+    /// won, drawn once no move is left, or still
+    /// in progress
+    pub fn status(&self) -> Status {
+        match self.winner() {
+            Cell::X => Status::Won(Player::X),
+            Cell::O => Status::Won(Player::O),
+            _ if self.legal_moves().is_empty() => Status::Drawn,
+            _ => Status::InProgress,
+        }
+    }
+
+    /// plays on a tile of a local board and
+    /// returns the new game without changing this
+    /// one. Errors name tiles by their index in
+    /// the array
+    pub fn turn(&self, board: u16, tile: u16) -> Result<Self, GameError> {
+        if board >= SIZE || tile >= SIZE {
+            return Err(GameError::OutOfBounds(board.saturating_mul(SIZE).saturating_add(tile)));
+        }
+        let winner = self.winner();
+        if winner != Cell::N {
+            return Err(GameError::GameWon(winner));
+        }
+        match self.next_board() {
+            Some(next) if next != board => return Err(GameError::WrongBoard(next)),
+            _ => {},
+        }
+        if !self.is_open(board) {
+            return Err(GameError::BoardClosed(board));
+        }
+
+        let mut new = self.clone();
+        if new.last >= RENUMBER_AT {
+            new.renumber();
+        }
+        // the local board carries on from the last
+        // move of the whole game
+        let mut local = new.boards[board as usize].clone();
+        local.last = new.last;
+        // the expiring mark of the board is
+        // removed by the move
+        let local = local.turn(tile).map_err(|error| match error {
+            GameError::OccupiedCell(_) => GameError::OccupiedCell(board * SIZE + tile),
+            GameError::ExpiringCell(_) => GameError::ExpiringCell(board * SIZE + tile),
+            GameError::OutOfBounds(_) => GameError::OutOfBounds(board * SIZE + tile),
+            GameError::FloatingTile(_) => GameError::FloatingTile(board * SIZE + tile),
+            error => error,
+        })?;
+        new.last = local.last;
+
+        let start = usize::from(board * SIZE);
+        new.array[start..start + usize::from(SIZE)].copy_from_slice(&local.array);
+        new.update();
+        Ok(new)
+    }
+
+    /// the tile of the mark the next move on a
+    /// local board removes, None if it keeps
+    /// all its marks
+    pub fn expiring_tile(&self, board: u16) -> Option<u16> {
+        let local = self.board(board);
+        (local.expiring != 0).then(|| local.array.iter().position(|&n| n == local.expiring).unwrap() as u16)
+    }
+
+    /// private function. Builds every local board
+    /// from the array and marks their expiring
+    /// marks for the player to move, before
+    /// anything looks at the lines of a board
+    fn update(&mut self) {
+        let rules = Rules::classic().with_first(self.first);
+        self.boards = self.array
+        .chunks(usize::from(SIZE))
+        .map(|local| GameState::with_rules(rules, local.to_vec()))
+        .collect();
+        for board in 0..SIZE {
+            if let Some(tile) = self.oldest(board) {
+                self.boards[board as usize].set_expiring(tile);
+            }
+        }
+    }
+
+    /// private function. The tile of the mark the
+    /// next move on a local board removes, see
+    /// `Rules::expiring_move`. Only the marks on
+    /// that board count towards the window
+    fn oldest(&self, board: u16) -> Option<u16> {
+        let local = &self.array[usize::from(board * SIZE)..usize::from((board + 1) * SIZE)];
+        let rules = self.rules();
+        let next = self.last + 1;
+        let moves = local.iter().filter(|&&n| n != 0 && rules.counts(n, next)).count() as u16;
+        let oldest = rules.expiring_after(moves, next, local)?;
+        local.iter().position(|&n| n == oldest).map(|tile| tile as u16)
+    }
+
+    /// private function. Renumbers the marks of
+    /// every board together, see
+    /// `GameState::renumber`
+    fn renumber(&mut self) {
+        let rules = Rules::new(Board::new(SIZE * SIZE, 1, 0), Expiry::Never).with_first(self.first);
+        let mut all = GameState::with_rules(rules, self.array.clone());
+        all.renumber();
+        self.array = all.array;
+        self.last = all.last;
+        self.update();
+    }
+}
+
+#[wasm_bindgen]
+impl Ultimate {
+    #[wasm_bindgen(constructor)]
+    /// WASM Function
+    /// an empty game, the window, `per_player`
+    /// and `first` are read like `Rules::sized`
+    /// and apply to every local board
+    pub fn create(window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Self {
+        Self::with_rules(&Rules::sized(3, 3, 3, window, per_player, first))
+    }
+
+    /// WASM Function
+    /// an empty game with the expiry and first
+    /// player of the rules, `Rules.classic()` for
+    /// marks that never expire
+    pub fn with_rules(rules: &Rules) -> Self {
+        Self::new(rules.expiry, rules.first)
+    }
+
+    /// WASM Function
+    /// resumes a game from a saved array, throws
+    /// if it is not a valid position
+    pub fn from_array(array: Vec<u16>, window: Option<u16>, per_player: Option<bool>, first: Option<i32>) -> Result<Ultimate, JsValue> {
+        Ultimate::from_array_with(array, &Rules::sized(3, 3, 3, window, per_player, first))
+    }
+
+    /// WASM Function
+    /// the same as `from_array` with the expiry
+    /// and first player of the rules
+    pub fn from_array_with(array: Vec<u16>, rules: &Rules) -> Result<Ultimate, JsValue> {
+        Ok(Self::load(rules.expiry, rules.first, &array)?)
+    }
+
+    /// WASM Function
+    /// plays on a tile of a local board and
+    /// returns the status, see `status`
+    pub fn play(&mut self, board: u16, tile: u16) -> Result<i32, JsValue> {
+        *self = self.turn(board, tile)?;
+        Ok(self.status().number())
+    }
+
+    /// WASM Function
+    /// the local board the player to move must
+    /// play on, None if any open board will do
+    pub fn next_board(&self) -> Option<u16> {
+        if self.last == 0 {
+            return None;
+        }
+        let index = self.array.iter().position(|&n| n == self.last)? as u16;
+        Some(index % SIZE).filter(|&board| self.is_open(board))
+    }
+
+    /// WASM Function
+    /// every move the player to move can make, as
+    /// indices of the array: the board times 9
+    /// plus the tile
+    pub fn legal_moves(&self) -> Vec<u16> {
+        if self.winner() != Cell::N {
+            return Vec::new();
+        }
+        let boards = match self.next_board() {
+            Some(board) => board..board + 1,
+            None => 0..SIZE,
+        };
+        boards
+        .flat_map(|board| self.board(board).legal_moves().into_iter().map(move |tile| board * SIZE + tile))
+        .collect()
+    }
+
+    /// WASM Function
+    /// the array of move numbers, for saving
+    pub fn array(&self) -> Vec<u16> {
+        self.array.clone()
+    }
+
+    /// WASM Function
+    /// the owner of every tile, 1 = X, -1 = O and
+    /// 0 is empty
+    pub fn cells(&self) -> Vec<i32> {
+        (0..SIZE * SIZE).map(|index| self.get(index).number()).collect()
+    }
+
+    /// WASM Function
+    /// the array index of the expiring mark of
+    /// every local board that has one
+    pub fn expiring_tiles(&self) -> Vec<u16> {
+        (0..SIZE)
+        .filter_map(|board| self.expiring_tile(board).map(|tile| board * SIZE + tile))
+        .collect()
+    }
+
+    /// WASM Function
+    /// the status of every local board, the same
+    /// numbers as `status`
+    pub fn board_status(&self) -> Vec<i32> {
+        self.boards.iter().map(|local| local.status().number()).collect()
+    }
+
+    #[wasm_bindgen(js_name = status)]
+    /// WASM Function
+    /// 1 = X won, -1 = O won, 0 means the game
+    /// goes on and 2 that it is drawn
+    pub fn status_number(&self) -> i32 {
+        self.status().number()
+    }
+
+    /// WASM Function
+    /// 1 = X, -1 = O
+    pub fn current_player(&self) -> i32 {
+        self.to_move().number()
+    }
+}
+
+
+
+
+
+
+
+
+
+
+#[cfg(test)]
+mod test {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use super::Ultimate;
+    use crate::error::GameError;
+    use crate::models::{Cell, Expiry, Player, Status, RENUMBER_AT};
+
+    /// plays (board, tile) moves in order
+    fn play(moves: &[(u16, u16)], expiry: Expiry) -> Ultimate {
+        moves.iter().fold(Ultimate::new(expiry, Player::O), |game, &(board, tile)| game.turn(board, tile).unwrap())
+    }
+
+    #[test]
+    /// every move sends the opponent to the board
+    /// of its tile
+    fn ultimate_sends_opponent() {
+        let game = Ultimate::new(Expiry::Never, Player::O);
+        assert_eq!(game.next_board(), None);
+        assert_eq!(game.legal_moves().len(), 81);
+
+        let game = game.turn(4, 2).unwrap();
+        assert_eq!(game.next_board(), Some(2));
+        assert_eq!(game.legal_moves(), (18..27).collect::<Vec<u16>>());
+        assert_eq!(game.get(4 * 9 + 2), Cell::O);
+        assert_eq!(game.to_move(), Player::X);
+        assert_eq!(game.turn(3, 0), Err(GameError::WrongBoard(2)));
+        assert_eq!(game.turn(2, 9), Err(GameError::OutOfBounds(27)));
+
+        // sent back to the same board, which is
+        // fine as long as the tile is free
+        let game = game.turn(2, 4).unwrap();
+        assert_eq!(game.turn(4, 2), Err(GameError::OccupiedCell(38)));
+        assert_eq!(game.board(4).array, vec![0, 0, 1, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    /// a won board is closed and sending a player
+    /// there lets them play anywhere
+    fn ultimate_closed_board() {
+        // X takes the middle row of board 0
+        let game = play(&[(0, 0), (0, 3), (3, 0), (0, 4), (4, 0), (0, 5)], Expiry::Never);
+        assert_eq!(game.board(0).winner(), Cell::X);
+        assert!(!game.is_open(0));
+        assert_eq!(game.board_status()[0], 1);
+
+        // sent to the closed board 0
+        let game = game.turn(5, 0).unwrap();
+        assert_eq!(game.next_board(), None);
+        assert_eq!(game.turn(0, 6), Err(GameError::BoardClosed(0)));
+        assert!(game.legal_moves().iter().all(|&index| index >= 9));
+        assert!(game.turn(8, 8).is_ok());
+    }
+
+    #[test]
+    fn ultimate_meta_win() {
+        // O wins the top row of board 4, 0 and 8
+        let game = play(&[
+            (4, 0), (0, 4), (4, 1), (1, 4), (4, 2), (2, 0),
+            (0, 0), (0, 5), (5, 8), (8, 4), (0, 1), (1, 0),
+            (0, 2), (2, 8), (8, 0), (6, 8), (8, 1),
+        ], Expiry::Never);
+        assert_eq!(game.board(4).winner(), Cell::O);
+        assert_eq!(game.board(0).winner(), Cell::O);
+        assert_eq!(game.status(), Status::InProgress);
+
+        let game = game.turn(1, 8).unwrap().turn(8, 2).unwrap();
+        assert_eq!(game.board(8).winner(), Cell::O);
+        assert_eq!(game.winner(), Cell::O);
+        assert_eq!(game.status(), Status::Won(Player::O));
+        assert!(game.legal_moves().is_empty());
+        assert_eq!(game.turn(2, 0), Err(GameError::GameWon(Cell::O)));
+    }
+
+    #[test]
+    /// each local board keeps its own window
+    fn ultimate_expiry() {
+        let moves = [(0, 0), (0, 1), (1, 0), (0, 2), (2, 0), (0, 3)];
+        let game = play(&moves, Expiry::Global(3));
+        // board 0 got four marks, the oldest left
+        assert_eq!(game.board(0).array, vec![0, 2, 4, 6, 0, 0, 0, 0, 0]);
+        assert_eq!(game.get(0), Cell::N);
+        assert_eq!(game.get(1), Cell::E);
+        assert_eq!(game.expiring_tiles(), vec![1]);
+        assert_eq!(game.board(1).array[0], 3);
+
+        // the expiring mark can not be played on
+        // and the next move on its board removes it
+        let game = play(&[(4, 4), (4, 0), (0, 4)], Expiry::Global(2));
+        assert_eq!(game.get(40), Cell::E);
+        assert_eq!(game.turn(4, 4), Err(GameError::ExpiringCell(40)));
+        assert_eq!(game.turn(4, 0), Err(GameError::OccupiedCell(36)));
+        let game = game.turn(4, 8).unwrap();
+        assert_eq!(game.board(4).array, vec![2, 0, 0, 0, 0, 0, 0, 0, 4]);
+        assert_eq!(game.get(36), Cell::E);
+
+        // the expiring mark does not complete O's
+        // line, so board 0 stays open
+        let game = play(&[(0, 3), (3, 0), (0, 4), (4, 0), (0, 5)], Expiry::Global(3));
+        assert_eq!(game.expiring_tile(0), Some(3));
+        assert_eq!(game.board(0).status(), Status::InProgress);
+        assert!(game.is_open(0));
+
+        let game = play(&moves, Expiry::PerPlayer(1));
+        // X's 2 made way for its 4, then 6
+        assert_eq!(game.board(0).array, vec![1, 0, 0, 6, 0, 0, 0, 0, 0]);
+        assert_eq!(Ultimate::load(Expiry::PerPlayer(1), Player::O, &game.array()), Ok(game));
+    }
+
+    #[test]
+    fn ultimate_load() {
+        let game = play(&[(4, 2), (2, 4), (4, 7)], Expiry::Never);
+        assert_eq!(Ultimate::load(Expiry::Never, Player::O, &game.array()), Ok(game.clone()));
+        assert_eq!(game.next_board(), Some(7));
+
+        assert_eq!(
+            Ultimate::load(Expiry::Never, Player::O, &[0; 9]),
+            Err(GameError::WrongLength { expected: 81, found: 9 }),
+        );
+        let mut array = game.array();
+        array[0] = 1;
+        assert_eq!(Ultimate::load(Expiry::Never, Player::O, &array), Err(GameError::DuplicateMove(1)));
+        let array = play(&[(0, 0), (0, 1), (1, 0), (0, 2)], Expiry::Never).array();
+        assert_eq!(
+            Ultimate::load(Expiry::Global(2), Player::O, &array),
+            Err(GameError::TooManyMarks { count: 3, window: 2 }),
+        );
+    }
+
+    #[test]
+    /// random games end properly and long games
+    /// with expiring marks keep their numbers low
+    fn ultimate_random_games() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for expiry in [Expiry::Never, Expiry::Global(4), Expiry::PerPlayer(2)] {
+            for _ in 0..20 {
+                let mut game = Ultimate::new(expiry, Player::X);
+                for _ in 0..300 {
+                    let moves = game.legal_moves();
+                    if moves.is_empty() {
+                        assert_ne!(game.status(), Status::InProgress);
+                        break;
+                    }
+                    assert_eq!(game.status(), Status::InProgress);
+                    let index = moves[rng.gen_range(0..moves.len())];
+                    game = game.turn(index / 9, index % 9).unwrap();
+                }
+                assert_eq!(Ultimate::load(expiry, Player::X, &game.array()), Ok(game));
+            }
+        }
+
+        let mut array = vec![0; 81];
+        array[0] = RENUMBER_AT - 1;
+        array[40] = RENUMBER_AT;
+        let game = Ultimate::load(Expiry::Global(4), Player::O, &array).unwrap();
+        assert_eq!(game.array()[40], 2);
+        let game = game.turn(4, 0).unwrap();
+        assert_eq!(game.get(40), Cell::X);
+        assert_eq!(game.get(36), Cell::O);
+        assert!(game.array().iter().all(|&n| n < 10));
+    }
+}
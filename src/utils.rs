@@ -1,44 +1,52 @@
-use crate::GameState;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Helper function used for converting
-/// the Vector received from javascript
-/// into an array for use in rust code
-pub(crate) fn state_vec_to_array(array: Vec<u16>) -> [u16; 9] {
-    let mut new = [0;9];
-    for (i, x) in array.iter().take(9).enumerate() {
-        new[i] = *x;
+use crate::models::GameState;
+
+/// prints the grid, with a blank line between
+/// the layers of a cubic board
+pub fn print_state(game_state: &GameState) {
+    let height = game_state.board().height as usize;
+    for (i, row) in game_state.state.iter().enumerate() {
+        if i > 0 && i % height == 0 {
+            println!();
+        }
+        let cells: Vec<String> = row.iter().map(|cell| format!("{:?}", cell)).collect();
+        println!("|{}|", cells.join("|"));
     }
-    new
 }
 
-/// The rows, columns and diagonals where
-/// the game can be won, winning checks 
-/// happen on these
-pub(crate) fn get_possibilities() -> Vec<[u16; 3]> {
-    vec![
-        [0, 1, 2],
-        [3, 4, 5],
-        [6, 7, 8],
-
-        [0, 3, 6],
-        [1, 4, 7],
-        [2, 5, 8],
-
-        [0, 4, 8],
-        [2, 4, 6],
-    ]
+/// the date in UTC as YYYY-MM-DD, from the
+/// browser's clock in wasm
+#[cfg(target_arch = "wasm32")]
+pub fn today() -> String {
+    let iso = String::from(js_sys::Date::new_0().to_iso_string());
+    iso.chars().take(10).collect()
 }
 
-pub fn print_state(array: &[u16;9]) {
-    let gs = GameState::from_array(array.to_owned());
-
-    println!("|{:?}|{:?}|{:?}|", gs.get(0), gs.get(1), gs.get(2));
-    println!("|{:?}|{:?}|{:?}|", gs.get(3), gs.get(4), gs.get(5));
-    println!("|{:?}|{:?}|{:?}|", gs.get(6), gs.get(7), gs.get(8));
+/// the date in UTC as YYYY-MM-DD, from the
+/// system clock
+#[cfg(not(target_arch = "wasm32"))]
+pub fn today() -> String {
+    let seconds = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |duration| duration.as_secs());
+    // days since 1970-01-01 to a civil date, from
+    // Howard Hinnant's days_from_civil inverse
+    let z = (seconds / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
 }
 
 pub fn print_values(array: &[i32;9]) {
     println!("|{:?}|{:?}|{:?}|", array[0], array[1], array[2]);
     println!("|{:?}|{:?}|{:?}|", array[3], array[4], array[5]);
     println!("|{:?}|{:?}|{:?}|", array[6], array[7], array[8]);
-}
\ No newline at end of file
+}